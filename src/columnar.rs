@@ -0,0 +1,440 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{BinaryBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder,
+                           TimestampMicrosecondBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+#[cfg(feature = "arrow-output")]
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::prelude::*;
+use mysql::consts::{ColumnFlags, ColumnType};
+#[cfg(feature = "parquet-output")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "parquet-output")]
+use parquet::basic::Compression;
+#[cfg(feature = "parquet-output")]
+use parquet::file::properties::WriterProperties;
+
+use crate::error::{Error, Result};
+use crate::output::RowWriter;
+use crate::value::{to_naive_datetime, Converter, ZeroDate};
+use crate::zone::Zone;
+
+
+/// Character set number the server reports for binary strings.
+const BINARY_CHARSET: u16 = 63;
+
+/// Maps the metadata of a column onto the Arrow type its values are stored as.
+fn data_type(column: &mysql::Column, tz: Option<Zone>) -> DataType {
+    use ColumnType::*;
+    match column.column_type() {
+        MYSQL_TYPE_TINY | MYSQL_TYPE_SHORT | MYSQL_TYPE_LONG | MYSQL_TYPE_INT24 | MYSQL_TYPE_YEAR => DataType::Int64,
+        MYSQL_TYPE_LONGLONG => {
+            if column.flags().contains(ColumnFlags::UNSIGNED_FLAG) {
+                DataType::UInt64
+            }
+            else {
+                DataType::Int64
+            }
+        },
+        MYSQL_TYPE_FLOAT | MYSQL_TYPE_DOUBLE => DataType::Float64,
+        MYSQL_TYPE_DATE | MYSQL_TYPE_NEWDATE => DataType::Date32,
+        MYSQL_TYPE_DATETIME | MYSQL_TYPE_DATETIME2 | MYSQL_TYPE_TIMESTAMP | MYSQL_TYPE_TIMESTAMP2 => {
+            DataType::Timestamp(TimeUnit::Microsecond, tz.map(|tz| tz.to_string().into()))
+        },
+        MYSQL_TYPE_BIT | MYSQL_TYPE_GEOMETRY => DataType::Binary,
+        MYSQL_TYPE_TINY_BLOB | MYSQL_TYPE_MEDIUM_BLOB | MYSQL_TYPE_LONG_BLOB | MYSQL_TYPE_BLOB |
+        MYSQL_TYPE_VAR_STRING | MYSQL_TYPE_STRING | MYSQL_TYPE_VARCHAR if column.character_set() == BINARY_CHARSET => {
+            DataType::Binary
+        },
+        _ => DataType::Utf8,
+    }
+}
+
+enum ColumnBuilder {
+    Int64(Int64Builder),
+    UInt64(UInt64Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+    Date32(Date32Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> ColumnBuilder {
+        match *data_type {
+            DataType::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+            DataType::UInt64 => ColumnBuilder::UInt64(UInt64Builder::new()),
+            DataType::Float64 => ColumnBuilder::Float64(Float64Builder::new()),
+            DataType::Binary => ColumnBuilder::Binary(BinaryBuilder::new()),
+            DataType::Date32 => ColumnBuilder::Date32(Date32Builder::new()),
+            DataType::Timestamp(_, ref tz) => {
+                ColumnBuilder::Timestamp(TimestampMicrosecondBuilder::new().with_timezone_opt(tz.clone()))
+            },
+            _ => ColumnBuilder::Utf8(StringBuilder::new()),
+        }
+    }
+
+    fn append(&mut self, i: usize, val: &mysql::Value, conv: &Converter) -> Result<()> {
+        if *val == mysql::Value::NULL {
+            match *self {
+                ColumnBuilder::Int64(ref mut b) => b.append_null(),
+                ColumnBuilder::UInt64(ref mut b) => b.append_null(),
+                ColumnBuilder::Float64(ref mut b) => b.append_null(),
+                ColumnBuilder::Utf8(ref mut b) => b.append_null(),
+                ColumnBuilder::Binary(ref mut b) => b.append_null(),
+                ColumnBuilder::Date32(ref mut b) => b.append_null(),
+                ColumnBuilder::Timestamp(ref mut b) => b.append_null(),
+            }
+            return Ok(());
+        }
+        let invalid = || Error::Value(format!("cannot store {:?} in a columnar output", val));
+        match *self {
+            ColumnBuilder::Int64(ref mut b) => b.append_value(mysql::from_value_opt(val.clone()).map_err(|_| invalid())?),
+            ColumnBuilder::UInt64(ref mut b) => b.append_value(mysql::from_value_opt(val.clone()).map_err(|_| invalid())?),
+            ColumnBuilder::Float64(ref mut b) => b.append_value(mysql::from_value_opt(val.clone()).map_err(|_| invalid())?),
+            ColumnBuilder::Utf8(ref mut b) => b.append_value(conv.text(i, val)?),
+            ColumnBuilder::Binary(ref mut b) => {
+                match *val {
+                    mysql::Value::Bytes(ref bytes) => b.append_value(bytes),
+                    _ => b.append_value(conv.text(i, val)?),
+                }
+            },
+            ColumnBuilder::Date32(ref mut b) => {
+                match valid_date(val, conv)? {
+                    Some(naive) => b.append_value((naive.date() - NaiveDate::default()).num_days() as i32),
+                    None => b.append_null(),
+                }
+            },
+            ColumnBuilder::Timestamp(ref mut b) => {
+                let naive = match valid_date(val, conv)? {
+                    Some(naive) => naive,
+                    None => {
+                        b.append_null();
+                        return Ok(());
+                    },
+                };
+                let micros = match conv.tz() {
+                    Some(tz) => tz.to_utc(&naive).ok_or_else(invalid)?.timestamp_micros(),
+                    None => naive.and_utc().timestamp_micros(),
+                };
+                b.append_value(micros);
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match *self {
+            ColumnBuilder::Int64(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::UInt64(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Float64(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Utf8(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Binary(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Date32(ref mut b) => Arc::new(b.finish()),
+            ColumnBuilder::Timestamp(ref mut b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Reads a DATE or DATETIME value, giving `None` for a zero date or another
+/// date which does not exist, unless `--zero-date error` says to fail.
+fn valid_date(val: &mysql::Value, conv: &Converter) -> Result<Option<NaiveDateTime>> {
+    match (to_naive_datetime(val), val) {
+        (Some(naive), _) => Ok(Some(naive)),
+        (None, &mysql::Value::Date(..)) if conv.zero_date() != ZeroDate::Error => Ok(None),
+        _ => Err(Error::Value(format!("cannot store {:?} in a columnar output", val))),
+    }
+}
+
+/// Accumulates rows of a result set into Arrow record batches.
+pub struct BatchBuilder {
+    schema: SchemaRef,
+    columns: Vec<ColumnBuilder>,
+    rows: usize,
+    conv: Converter,
+}
+
+impl BatchBuilder {
+    pub fn new(columns: &[mysql::Column], mut conv: Converter) -> BatchBuilder {
+        conv.begin(columns);
+        let fields: Vec<Field> = columns.iter().map(|c| {
+            let data_type = data_type(c, conv.tz());
+            // Zero dates are stored as nulls, even in NOT NULL columns.
+            let nullable = !c.flags().contains(ColumnFlags::NOT_NULL_FLAG) || matches!(data_type, DataType::Date32 | DataType::Timestamp(..));
+            Field::new(c.name_str(), data_type, nullable)
+        }).collect();
+        BatchBuilder {
+            columns: fields.iter().map(|f| ColumnBuilder::new(f.data_type())).collect(),
+            schema: Arc::new(Schema::new(fields)),
+            rows: 0,
+            conv,
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Number of rows appended since the last batch was taken.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn append(&mut self, row: &mysql::Row) -> Result<()> {
+        for (i, column) in self.columns.iter_mut().enumerate() {
+            column.append(i, &row[i], &self.conv)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Takes the rows appended so far as a record batch.
+    pub fn finish(&mut self) -> Result<RecordBatch> {
+        let arrays = self.columns.iter_mut().map(|c| c.finish()).collect();
+        self.rows = 0;
+        Ok(RecordBatch::try_new(self.schema.clone(), arrays)?)
+    }
+}
+
+/// Writes a single result set to a Parquet file, one row group every
+/// `row_group_size` rows.
+#[cfg(feature = "parquet-output")]
+pub struct ParquetWriter<W: Write + Send> {
+    out: Option<W>,
+    writer: Option<ArrowWriter<W>>,
+    batch: Option<BatchBuilder>,
+    row_group_size: usize,
+    conv: Converter,
+}
+
+#[cfg(feature = "parquet-output")]
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(out: W, conv: Converter, row_group_size: usize) -> ParquetWriter<W> {
+        ParquetWriter {
+            out: Some(out),
+            writer: None,
+            batch: None,
+            row_group_size,
+            conv,
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if let (Some(writer), Some(batch)) = (self.writer.as_mut(), self.batch.as_mut()) {
+            if batch.len() > 0 {
+                writer.write(&batch.finish()?)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet-output")]
+impl<W: Write + Send> RowWriter for ParquetWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let out = self.out.take().ok_or_else(|| {
+            Error::Usage("parquet output can only hold a single result set".to_owned())
+        })?;
+        let batch = BatchBuilder::new(columns, self.conv.clone());
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(self.row_group_size)
+            .build();
+        self.writer = Some(ArrowWriter::try_new(out, batch.schema(), Some(props))?);
+        self.batch = Some(batch);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        if let Some(batch) = self.batch.as_mut() {
+            batch.append(row)?;
+            if batch.len() >= self.row_group_size {
+                self.write_batch()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.write_batch()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a single result set as an Arrow IPC stream.
+///
+/// Rows are sent in record batches of `batch_size` rows, and whatever is
+/// pending is sent as a shorter batch on `flush`, so `tail` output is not held
+/// back while waiting for a batch to fill.
+#[cfg(feature = "arrow-output")]
+pub struct ArrowStreamWriter<W: Write> {
+    out: Option<W>,
+    writer: Option<StreamWriter<W>>,
+    batch: Option<BatchBuilder>,
+    batch_size: usize,
+    conv: Converter,
+}
+
+#[cfg(feature = "arrow-output")]
+impl<W: Write> ArrowStreamWriter<W> {
+    pub fn new(out: W, conv: Converter, batch_size: usize) -> ArrowStreamWriter<W> {
+        ArrowStreamWriter {
+            out: Some(out),
+            writer: None,
+            batch: None,
+            batch_size,
+            conv,
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if let (Some(writer), Some(batch)) = (self.writer.as_mut(), self.batch.as_mut()) {
+            if batch.len() > 0 {
+                writer.write(&batch.finish()?)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "arrow-output")]
+impl<W: Write> RowWriter for ArrowStreamWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let out = self.out.take().ok_or_else(|| {
+            Error::Usage("arrow output can only hold a single result set".to_owned())
+        })?;
+        let batch = BatchBuilder::new(columns, self.conv.clone());
+        self.writer = Some(StreamWriter::try_new(out, &batch.schema())?);
+        self.batch = Some(batch);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        if let Some(batch) = self.batch.as_mut() {
+            batch.append(row)?;
+            if batch.len() >= self.batch_size {
+                self.write_batch()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.write_batch()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_batch()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_batch()?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Date32Type, Float64Type, Int64Type, TimestampMicrosecondType, UInt64Type};
+    use arrow_array::Array;
+    use mysql::Value::{Bytes, Date, Float, Int, UInt, NULL};
+
+    use crate::output::make_column;
+    use crate::tests::{options, row};
+
+    fn column(name: &str, column_type: ColumnType, flags: ColumnFlags, charset: u16) -> mysql::Column {
+        make_column(name, column_type, flags, charset, 0, 0)
+    }
+
+    fn columns() -> Vec<mysql::Column> {
+        use ColumnType::*;
+        let number = ColumnFlags::BINARY_FLAG;
+        vec![
+            column("i", MYSQL_TYPE_LONGLONG, number | ColumnFlags::NOT_NULL_FLAG, BINARY_CHARSET),
+            column("u", MYSQL_TYPE_LONGLONG, number | ColumnFlags::UNSIGNED_FLAG, BINARY_CHARSET),
+            column("f", MYSQL_TYPE_DOUBLE, number, BINARY_CHARSET),
+            column("s", MYSQL_TYPE_VAR_STRING, ColumnFlags::empty(), 45),
+            column("b", MYSQL_TYPE_BLOB, ColumnFlags::BINARY_FLAG | ColumnFlags::BLOB_FLAG, BINARY_CHARSET),
+            column("d", MYSQL_TYPE_DATE, number | ColumnFlags::NOT_NULL_FLAG, BINARY_CHARSET),
+            column("t", MYSQL_TYPE_DATETIME, number, BINARY_CHARSET),
+        ]
+    }
+
+    fn batch_builder(args: &[&str]) -> BatchBuilder {
+        BatchBuilder::new(&columns(), Converter::new(options(args).values))
+    }
+
+    #[test]
+    fn batch_of_each_type() {
+        let columns = columns();
+        let mut builder = batch_builder(&[]);
+        builder.append(&row(&columns, vec![
+            Int(-1), UInt(u64::MAX), Float(1.5), Bytes("ä".into()), Bytes(vec![0, 255]),
+            Date(2024, 2, 29, 0, 0, 0, 0), Date(2024, 2, 29, 12, 34, 56, 789),
+        ])).unwrap();
+        builder.append(&row(&columns, vec![Int(i64::MAX), NULL, NULL, NULL, NULL, Date(1970, 1, 2, 0, 0, 0, 0), NULL])).unwrap();
+        assert_eq!(builder.len(), 2);
+        let batch = builder.finish().unwrap();
+        assert_eq!(builder.len(), 0);
+
+        let types = batch.schema().fields().iter().map(|f| (f.data_type().clone(), f.is_nullable())).collect::<Vec<_>>();
+        assert_eq!(types, vec![
+            (DataType::Int64, false),
+            (DataType::UInt64, true),
+            (DataType::Float64, true),
+            (DataType::Utf8, true),
+            (DataType::Binary, true),
+            (DataType::Date32, true),
+            (DataType::Timestamp(TimeUnit::Microsecond, None), true),
+        ]);
+        assert_eq!(batch.column(0).as_primitive::<Int64Type>().values(), &[-1, i64::MAX]);
+        let u = batch.column(1).as_primitive::<UInt64Type>();
+        assert_eq!((u.value(0), u.is_null(1)), (u64::MAX, true));
+        let f = batch.column(2).as_primitive::<Float64Type>();
+        assert_eq!((f.value(0), f.is_null(1)), (1.5, true));
+        let s = batch.column(3).as_string::<i32>();
+        assert_eq!((s.value(0), s.is_null(1)), ("ä", true));
+        let b = batch.column(4).as_binary::<i32>();
+        assert_eq!((b.value(0), b.is_null(1)), (&[0, 255][..], true));
+        assert_eq!(batch.column(5).as_primitive::<Date32Type>().values(), &[19782, 1]);
+        let t = batch.column(6).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!((t.value(0), t.is_null(1)), (1_709_210_096_000_789, true));
+    }
+
+    #[test]
+    fn zero_dates() {
+        let columns = columns();
+        let zero = row(&columns, vec![Int(1), UInt(1), Float(1.0), NULL, NULL, Date(0, 0, 0, 0, 0, 0, 0), Date(2024, 2, 30, 0, 0, 0, 0)]);
+        let mut builder = batch_builder(&[]);
+        builder.append(&zero).unwrap();
+        let batch = builder.finish().unwrap();
+        assert!(batch.column(5).is_null(0) && batch.column(6).is_null(0));
+        assert!(batch_builder(&["--zero-date", "error"]).append(&zero).is_err());
+    }
+}
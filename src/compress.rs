@@ -0,0 +1,93 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use clap::arg_enum;
+use flate2::write::GzEncoder;
+
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Compression {
+        Gzip,
+        Zstd,
+    }
+}
+
+impl Compression {
+    /// Extension of files compressed with the algorithm.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write + Send>>),
+}
+
+/// An output stream which compresses everything written to it.
+///
+/// Clones share the same stream.  A row writer owns one of them while the
+/// caller keeps another to terminate the compressed stream with `finish` once
+/// the row writer is done with it.
+#[derive(Clone)]
+pub struct CompressedOutput {
+    encoder: Arc<Mutex<Option<Encoder>>>,
+}
+
+impl CompressedOutput {
+    pub fn new(out: Box<dyn Write + Send>, compression: Compression) -> io::Result<CompressedOutput> {
+        let encoder = match compression {
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(out, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(out, 0)?),
+        };
+        Ok(CompressedOutput {
+            encoder: Arc::new(Mutex::new(Some(encoder))),
+        })
+    }
+
+    /// Writes the trailer of the compressed stream and flushes the underlying
+    /// output.  Later writes fail.
+    pub fn finish(&self) -> io::Result<()> {
+        let encoder = self.encoder.lock().unwrap().take();
+        let mut out = match encoder {
+            Some(Encoder::Gzip(e)) => e.finish()?,
+            Some(Encoder::Zstd(e)) => e.finish()?,
+            None => return Ok(()),
+        };
+        out.flush()
+    }
+}
+
+impl Write for CompressedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self.encoder.lock().unwrap() {
+            Some(Encoder::Gzip(ref mut e)) => e.write(buf),
+            Some(Encoder::Zstd(ref mut e)) => e.write(buf),
+            None => Err(io::Error::other("compressed output already finished")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self.encoder.lock().unwrap() {
+            Some(Encoder::Gzip(ref mut e)) => e.flush(),
+            Some(Encoder::Zstd(ref mut e)) => e.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Compresses a file into one named with the extension of the algorithm
+/// appended, and removes it.
+pub fn compress_file(path: &str, compression: Compression) -> io::Result<()> {
+    let mut input = File::open(path)?;
+    let out = File::create(format!("{}.{}", path, compression.extension()))?;
+    let mut compressed = CompressedOutput::new(Box::new(BufWriter::new(out)), compression)?;
+    io::copy(&mut input, &mut compressed)?;
+    compressed.finish()?;
+    fs::remove_file(path)
+}
@@ -0,0 +1,232 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use mysql::consts::{ColumnFlags, ColumnType};
+
+use crate::error::{Error, Result};
+use crate::value::{hex, to_naive_datetime};
+
+
+/// Value of the column `tail` follows in the last row read.
+#[derive(Debug, Clone)]
+pub enum Cursor {
+    Int(i64),
+    UInt(u64),
+    /// Of a DATETIME, TIMESTAMP or DATE column.  Unlike an auto-increment key
+    /// it may be shared by several rows.
+    DateTime(NaiveDateTime),
+    /// Of a CHAR, VARCHAR, BINARY or VARBINARY column, compared byte by byte.
+    /// The server orders rows by the collation of the column instead, which
+    /// only agrees for binary strings and for text such as ULIDs in a single
+    /// letter case; with a case-insensitive collation, for one, `B` comes
+    /// after `a` there but before it here.
+    Bytes(Vec<u8>),
+}
+
+/// The sort of cursor a column gives.
+enum Kind {
+    Int,
+    UInt,
+    DateTime,
+    Bytes,
+}
+
+fn kind(column: &mysql::Column) -> Result<Kind> {
+    match column.column_type() {
+        ColumnType::MYSQL_TYPE_TINY
+        | ColumnType::MYSQL_TYPE_SHORT
+        | ColumnType::MYSQL_TYPE_INT24
+        | ColumnType::MYSQL_TYPE_LONG
+        | ColumnType::MYSQL_TYPE_LONGLONG
+        | ColumnType::MYSQL_TYPE_YEAR => {
+            Ok(if column.flags().contains(ColumnFlags::UNSIGNED_FLAG) { Kind::UInt } else { Kind::Int })
+        },
+        ColumnType::MYSQL_TYPE_DATE
+        | ColumnType::MYSQL_TYPE_NEWDATE
+        | ColumnType::MYSQL_TYPE_DATETIME
+        | ColumnType::MYSQL_TYPE_DATETIME2
+        | ColumnType::MYSQL_TYPE_TIMESTAMP
+        | ColumnType::MYSQL_TYPE_TIMESTAMP2 => Ok(Kind::DateTime),
+        ColumnType::MYSQL_TYPE_STRING
+        | ColumnType::MYSQL_TYPE_VAR_STRING
+        | ColumnType::MYSQL_TYPE_VARCHAR => Ok(Kind::Bytes),
+        _ => Err(Error::Usage(format!(
+            "column {} cannot be the cursor of tail: it has to be of an integer, date and time or string type",
+            column.name_str()))),
+    }
+}
+
+/// Decodes hex digits.
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok())).collect()
+}
+
+impl Cursor {
+    /// Checks that the column can be the cursor.
+    pub fn check_column(column: &mysql::Column) -> Result<()> {
+        kind(column).map(drop)
+    }
+
+    /// Whether the column gives date and time cursors.
+    pub fn is_datetime_column(column: &mysql::Column) -> bool {
+        matches!(kind(column), Ok(Kind::DateTime))
+    }
+
+    /// Takes the cursor from a value of the column.  NULL is none.
+    pub fn from_value(value: &mysql::Value) -> Result<Option<Cursor>> {
+        match *value {
+            mysql::Value::NULL => Ok(None),
+            mysql::Value::Int(n) => Ok(Some(Cursor::Int(n))),
+            mysql::Value::UInt(n) => Ok(Some(Cursor::UInt(n))),
+            // A zero date sorts before every real one, just like a NULL.
+            mysql::Value::Date(..) => Ok(to_naive_datetime(value).map(Cursor::DateTime)),
+            mysql::Value::Bytes(ref bytes) => Ok(Some(Cursor::Bytes(bytes.clone()))),
+            _ => Err(Error::Usage("the cursor column of tail has to be of an integer, date and time or string type".to_owned())),
+        }
+    }
+
+    /// Parses a cursor for the column given by `--since-id` or kept in a
+    /// state file.  A binary string which is not UTF-8 is written as `0x`
+    /// followed by hex digits.
+    pub fn parse(s: &str, column: &mysql::Column) -> Result<Cursor> {
+        let cursor = match kind(column)? {
+            Kind::Int => s.parse().ok().map(Cursor::Int),
+            Kind::UInt => s.parse().ok().map(Cursor::UInt),
+            Kind::DateTime => parse_datetime(s).map(Cursor::DateTime),
+            Kind::Bytes => match s.strip_prefix("0x") {
+                Some(digits) if column.character_set() == 63 => unhex(digits).map(Cursor::Bytes),
+                _ => Some(Cursor::Bytes(s.as_bytes().to_vec())),
+            },
+        };
+        cursor.ok_or_else(|| Error::Usage(format!("invalid cursor value for column {}: {}", column.name_str(), s)))
+    }
+
+    /// Whether several rows may have the same cursor, so that the polling
+    /// query has to include rows at the cursor and skip those already read.
+    pub fn is_shared(&self) -> bool {
+        matches!(*self, Cursor::DateTime(_))
+    }
+
+    /// How many values of an integer cursor lie after this one up to `max`,
+    /// which for an auto-increment key is about the number of rows.
+    pub fn lag(&self, max: &Cursor) -> Option<u64> {
+        let value = |cursor: &Cursor| match *cursor {
+            Cursor::Int(n) => Some(i128::from(n)),
+            Cursor::UInt(n) => Some(i128::from(n)),
+            _ => None,
+        };
+        Some((value(max)? - value(self)?).clamp(0, i128::from(u64::MAX)) as u64)
+    }
+
+    /// Moves a date and time cursor back by `window`.  Other cursors stay.
+    pub fn before(&self, window: std::time::Duration) -> Cursor {
+        match *self {
+            Cursor::DateTime(t) => Cursor::DateTime(chrono::Duration::from_std(window).ok()
+                .and_then(|window| t.checked_sub_signed(window))
+                .unwrap_or(NaiveDateTime::MIN)),
+            ref cursor => cursor.clone(),
+        }
+    }
+
+    /// The value to bind to the placeholder of the polling query.
+    pub fn to_value(&self) -> mysql::Value {
+        match *self {
+            Cursor::Int(n) => mysql::Value::Int(n),
+            Cursor::UInt(n) => mysql::Value::UInt(n),
+            Cursor::DateTime(t) => mysql::Value::Date(
+                t.year() as u16, t.month() as u8, t.day() as u8,
+                t.hour() as u8, t.minute() as u8, t.second() as u8, t.nanosecond() / 1000),
+            Cursor::Bytes(ref bytes) => mysql::Value::Bytes(bytes.clone()),
+        }
+    }
+}
+
+/// Parses a date and time such as `2024-05-01 12:00:00`, `2024-05-01T12:00:00.5`
+/// or `2024-05-01`.
+fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"].iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))
+}
+
+impl PartialEq for Cursor {
+    fn eq(&self, other: &Cursor) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Cursor {
+    /// Compares integers of either signedness by value.  Cursors of
+    /// different kinds are not comparable.
+    fn partial_cmp(&self, other: &Cursor) -> Option<Ordering> {
+        match (self, other) {
+            (Cursor::Int(a), Cursor::Int(b)) => a.partial_cmp(b),
+            (Cursor::UInt(a), Cursor::UInt(b)) => a.partial_cmp(b),
+            (Cursor::Int(a), Cursor::UInt(b)) => i128::from(*a).partial_cmp(&i128::from(*b)),
+            (Cursor::UInt(a), Cursor::Int(b)) => i128::from(*a).partial_cmp(&i128::from(*b)),
+            (Cursor::DateTime(a), Cursor::DateTime(b)) => a.partial_cmp(b),
+            (Cursor::Bytes(a), Cursor::Bytes(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cursor::Int(n) => n.fmt(f),
+            Cursor::UInt(n) => n.fmt(f),
+            Cursor::DateTime(t) => t.format("%Y-%m-%d %H:%M:%S%.f").fmt(f),
+            Cursor::Bytes(ref bytes) => match std::str::from_utf8(bytes) {
+                Ok(s) if !s.starts_with("0x") => f.write_str(s),
+                _ => write!(f, "0x{}", hex(bytes)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::make_column;
+
+    fn bigint(flags: ColumnFlags) -> mysql::Column {
+        make_column("id", ColumnType::MYSQL_TYPE_LONGLONG, flags | ColumnFlags::BINARY_FLAG, 63, 20, 0)
+    }
+
+    #[test]
+    fn large_and_negative_ids() {
+        let signed = bigint(ColumnFlags::empty());
+        let unsigned = bigint(ColumnFlags::UNSIGNED_FLAG);
+        let above_u32: i64 = 1 << 33;
+        assert_eq!(Cursor::parse("8589934592", &signed).unwrap(), Cursor::Int(above_u32));
+        assert_eq!(Cursor::parse("-42", &signed).unwrap(), Cursor::Int(-42));
+        assert_eq!(Cursor::parse("18446744073709551615", &unsigned).unwrap(), Cursor::UInt(u64::MAX));
+        assert!(Cursor::parse("-1", &unsigned).is_err());
+        assert!(Cursor::parse("18446744073709551615", &signed).is_err());
+        for s in &["8589934592", "-42", "18446744073709551615"] {
+            let column = if s.len() > 19 { &unsigned } else { &signed };
+            assert_eq!(Cursor::parse(s, column).unwrap().to_string(), *s);
+        }
+
+        assert!(Cursor::Int(-1) < Cursor::UInt(0));
+        assert!(Cursor::UInt(u64::MAX) > Cursor::Int(i64::MAX));
+        assert_eq!(Cursor::Int(above_u32), Cursor::UInt(above_u32 as u64));
+        assert!(Cursor::Int(i64::MIN) < Cursor::Int(-1));
+        assert_eq!(Cursor::Int(1).partial_cmp(&Cursor::Bytes(b"1".to_vec())), None);
+    }
+
+    #[test]
+    fn lag() {
+        assert_eq!(Cursor::Int(-5).lag(&Cursor::Int(5)), Some(10));
+        assert_eq!(Cursor::Int(i64::MIN).lag(&Cursor::UInt(u64::MAX)), Some(u64::MAX));
+        assert_eq!(Cursor::UInt(1 << 40).lag(&Cursor::UInt((1 << 40) + 3)), Some(3));
+        // The table may have shrunk since.
+        assert_eq!(Cursor::Int(10).lag(&Cursor::Int(3)), Some(0));
+        assert_eq!(Cursor::Bytes(b"a".to_vec()).lag(&Cursor::Int(3)), None);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+use std::io;
+
+use serde_json as json;
+
+
+/// Errors which abort the whole run.
+#[derive(Debug)]
+pub enum Error {
+    /// The command line is not usable as given.
+    Usage(String),
+    Io(io::Error),
+    /// An output file cannot be opened or put in place.
+    File(String, io::Error),
+    // Boxed as the driver's error type is several hundred bytes large.
+    Mysql(Box<mysql::Error>),
+    Csv(csv::Error),
+    Json(json::Error),
+    Msgpack(rmp_serde::encode::Error),
+    #[cfg(any(feature = "parquet-output", feature = "arrow-output"))]
+    Arrow(arrow_schema::ArrowError),
+    #[cfg(feature = "parquet-output")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "xlsx-output")]
+    Xlsx(rust_xlsxwriter::XlsxError),
+    /// A value cannot be represented in the requested output.
+    Value(String),
+    /// Connecting to or reading from the server, as named, took longer than
+    /// the given number of seconds.
+    Timeout(&'static str, u64),
+    /// The SSH tunnel to the server cannot be set up.
+    Tunnel(String),
+    /// Connecting to the given one of several hosts failed.
+    Host(String, Box<Error>),
+    /// None of several hosts could be reached, for the reasons given.
+    Hosts(Vec<(String, Error)>),
+    /// The columns `tail` reads changed, as the message says.
+    Schema(String),
+    /// The given init statement failed.
+    Init(String, Box<Error>),
+    /// Processing the statement with the given 1-based index failed.
+    Statement(usize, Box<Error>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Exit status reported to the shell for this error.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Usage(_) => 2,
+            Error::Timeout(..) => 3,
+            Error::Mysql(ref e) if is_access_denied(e) => 4,
+            Error::Tunnel(_) => 6,
+            _ if self.is_network() => 5,
+            Error::Host(_, ref e) | Error::Init(_, ref e) | Error::Statement(_, ref e) => e.exit_code(),
+            _ => 1,
+        }
+    }
+
+    /// Whether the server could not be reached or the connection to it
+    /// broke down.
+    pub fn is_network(&self) -> bool {
+        match *self {
+            Error::Timeout(..) | Error::Hosts(_) => true,
+            Error::Mysql(ref e) => matches!(**e,
+                mysql::Error::IoError(_)
+                | mysql::Error::DriverError(mysql::DriverError::CouldNotConnect(_))
+                | mysql::Error::DriverError(mysql::DriverError::ConnectTimeout)),
+            _ => false,
+        }
+    }
+
+    /// Whether the server rejected a statement, or the statement does not
+    /// fit its parameters, rather than the connection or the output failing.
+    pub fn is_rejected(&self) -> bool {
+        match *self {
+            Error::Usage(_) => true,
+            Error::Mysql(ref e) => matches!(**e, mysql::Error::MySqlError(_)),
+            Error::Statement(_, ref e) => e.is_rejected(),
+            _ => false,
+        }
+    }
+
+    /// Turns a driver error caused by the connect or the read timeout, given
+    /// in seconds, into `Error::Timeout`.
+    pub fn timed_out(self, connect: Option<u64>, read: Option<u64>) -> Error {
+        match self {
+            Error::Mysql(e) => match (*e, connect, read) {
+                (mysql::Error::DriverError(mysql::DriverError::ConnectTimeout), Some(secs), _) => Error::Timeout("connect", secs),
+                (mysql::Error::IoError(ref io), _, Some(secs)) if io.kind() == io::ErrorKind::TimedOut || io.kind() == io::ErrorKind::WouldBlock => Error::Timeout("read", secs),
+                (e, _, _) => Error::Mysql(Box::new(e)),
+            },
+            Error::Init(sql, e) => Error::Init(sql, Box::new(e.timed_out(connect, read))),
+            Error::Statement(n, e) => Error::Statement(n, Box::new(e.timed_out(connect, read))),
+            e => e,
+        }
+    }
+
+    /// Says which row a value error occurred in.  Rows are counted from 1.
+    pub fn at_row(self, n: u64) -> Error {
+        match self {
+            Error::Value(msg) => Error::Value(format!("row {}: {}", n, msg)),
+            e => e,
+        }
+    }
+}
+
+/// Whether the server refused the login or access to the database.
+fn is_access_denied(e: &mysql::Error) -> bool {
+    match *e {
+        // ER_DBACCESS_DENIED_ERROR, ER_ACCESS_DENIED_ERROR and
+        // ER_ACCESS_DENIED_NO_PASSWORD_ERROR
+        mysql::Error::MySqlError(ref e) => matches!(e.code, 1044 | 1045 | 1698),
+        _ => false,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref msg) => write!(f, "{}", msg),
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::File(ref path, ref e) => write!(f, "{}: {}", path, e),
+            Error::Mysql(ref e) => write!(f, "{}", e),
+            Error::Csv(ref e) => write!(f, "{}", e),
+            Error::Json(ref e) => write!(f, "{}", e),
+            Error::Msgpack(ref e) => write!(f, "{}", e),
+            #[cfg(any(feature = "parquet-output", feature = "arrow-output"))]
+            Error::Arrow(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "parquet-output")]
+            Error::Parquet(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "xlsx-output")]
+            Error::Xlsx(ref e) => write!(f, "{}", e),
+            Error::Value(ref msg) => write!(f, "{}", msg),
+            Error::Timeout(phase, secs) => write!(f, "{} timed out after {}s", phase, secs),
+            Error::Tunnel(ref msg) => write!(f, "SSH tunnel: {}", msg),
+            Error::Host(ref host, ref e) => write!(f, "{}: {}", host, e),
+            Error::Hosts(ref failures) => {
+                write!(f, "no host could be reached")?;
+                for (host, e) in failures {
+                    write!(f, "\n  {}: {}", host, e)?;
+                }
+                Ok(())
+            },
+            Error::Schema(ref msg) => write!(f, "{}", msg),
+            Error::Init(ref sql, ref e) => write!(f, "init statement {}: {}", sql, e),
+            Error::Statement(n, ref e) => write!(f, "statement {}: {}", n, e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<mysql::Error> for Error {
+    fn from(e: mysql::Error) -> Error {
+        Error::Mysql(Box::new(e))
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Error {
+        Error::Csv(e)
+    }
+}
+
+impl From<json::Error> for Error {
+    fn from(e: json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for Error {
+    fn from(e: rmp_serde::encode::Error) -> Error {
+        Error::Msgpack(e)
+    }
+}
+
+#[cfg(any(feature = "parquet-output", feature = "arrow-output"))]
+impl From<arrow_schema::ArrowError> for Error {
+    fn from(e: arrow_schema::ArrowError) -> Error {
+        Error::Arrow(e)
+    }
+}
+
+#[cfg(feature = "parquet-output")]
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(e: parquet::errors::ParquetError) -> Error {
+        Error::Parquet(e)
+    }
+}
+
+#[cfg(feature = "xlsx-output")]
+impl From<rust_xlsxwriter::XlsxError> for Error {
+    fn from(e: rust_xlsxwriter::XlsxError) -> Error {
+        Error::Xlsx(e)
+    }
+}
@@ -0,0 +1,180 @@
+use std::convert::TryInto;
+
+use serde_json as json;
+
+
+/// A geometry decoded from Well-Known Binary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(Option<(f64, f64)>),
+    LineString(Vec<(f64, f64)>),
+    Polygon(Vec<Vec<(f64, f64)>>),
+    MultiPoint(Vec<(f64, f64)>),
+    MultiLineString(Vec<Vec<(f64, f64)>>),
+    MultiPolygon(Vec<Vec<Vec<(f64, f64)>>>),
+    Collection(Vec<Geometry>),
+}
+
+/// Reads WKB, in which each geometry states its own byte order.
+struct Reader<'a> {
+    buf: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b: [u8; 4] = self.bytes(4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        let b: [u8; 8] = self.bytes(8)?.try_into().ok()?;
+        Some(if self.little_endian { f64::from_le_bytes(b) } else { f64::from_be_bytes(b) })
+    }
+
+    /// Reads a count and checks it against the remaining input, so a corrupt
+    /// count cannot cause a huge allocation.
+    fn count(&mut self, min_size: usize) -> Option<usize> {
+        let n = self.u32()? as usize;
+        if n.checked_mul(min_size)? > self.buf.len() {
+            return None;
+        }
+        Some(n)
+    }
+
+    fn point(&mut self) -> Option<(f64, f64)> {
+        Some((self.f64()?, self.f64()?))
+    }
+
+    fn points(&mut self) -> Option<Vec<(f64, f64)>> {
+        let n = self.count(16)?;
+        (0..n).map(|_| self.point()).collect()
+    }
+
+    fn rings(&mut self) -> Option<Vec<Vec<(f64, f64)>>> {
+        let n = self.count(4)?;
+        (0..n).map(|_| self.points()).collect()
+    }
+
+    fn geometries(&mut self) -> Option<Vec<Geometry>> {
+        let n = self.count(5)?;
+        (0..n).map(|_| self.geometry()).collect()
+    }
+
+    fn geometry(&mut self) -> Option<Geometry> {
+        self.little_endian = match self.u8()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(match self.u32()? {
+            1 => {
+                let (x, y) = self.point()?;
+                // An empty point is encoded with NaN coordinates.
+                Geometry::Point(if x.is_nan() && y.is_nan() { None } else { Some((x, y)) })
+            },
+            2 => Geometry::LineString(self.points()?),
+            3 => Geometry::Polygon(self.rings()?),
+            4 => Geometry::MultiPoint(self.geometries()?.into_iter().map(|g| match g {
+                Geometry::Point(Some(p)) => Some(p),
+                _ => None,
+            }).collect::<Option<_>>()?),
+            5 => Geometry::MultiLineString(self.geometries()?.into_iter().map(|g| match g {
+                Geometry::LineString(points) => Some(points),
+                _ => None,
+            }).collect::<Option<_>>()?),
+            6 => Geometry::MultiPolygon(self.geometries()?.into_iter().map(|g| match g {
+                Geometry::Polygon(rings) => Some(rings),
+                _ => None,
+            }).collect::<Option<_>>()?),
+            7 => Geometry::Collection(self.geometries()?),
+            _ => return None,
+        })
+    }
+}
+
+fn wkt_points(points: &[(f64, f64)]) -> String {
+    let points: Vec<String> = points.iter().map(|&(x, y)| format!("{} {}", x, y)).collect();
+    format!("({})", points.join(","))
+}
+
+fn wkt_rings(rings: &[Vec<(f64, f64)>]) -> String {
+    let rings: Vec<String> = rings.iter().map(|r| wkt_points(r)).collect();
+    format!("({})", rings.join(","))
+}
+
+fn geojson_points(points: &[(f64, f64)]) -> json::Value {
+    points.iter().map(|&(x, y)| json::json!([x, y])).collect()
+}
+
+fn geojson_rings(rings: &[Vec<(f64, f64)>]) -> json::Value {
+    rings.iter().map(|r| geojson_points(r)).collect()
+}
+
+impl Geometry {
+    /// Decodes the value of a GEOMETRY column: a 4-byte SRID followed by WKB.
+    pub fn from_mysql(bytes: &[u8]) -> Option<Geometry> {
+        if bytes.len() < 4 {
+            return None;
+        }
+        let mut reader = Reader { buf: &bytes[4..], little_endian: true };
+        let geometry = reader.geometry()?;
+        if reader.buf.is_empty() { Some(geometry) } else { None }
+    }
+
+    /// Formats the geometry as Well-Known Text like `ST_AsText` does.
+    pub fn to_wkt(&self) -> String {
+        match *self {
+            Geometry::Point(Some((x, y))) => format!("POINT({} {})", x, y),
+            Geometry::Point(None) => "POINT EMPTY".to_owned(),
+            Geometry::LineString(ref points) => format!("LINESTRING{}", wkt_points(points)),
+            Geometry::Polygon(ref rings) => format!("POLYGON{}", wkt_rings(rings)),
+            Geometry::MultiPoint(ref points) => {
+                let points: Vec<String> = points.iter().map(|&(x, y)| format!("({} {})", x, y)).collect();
+                format!("MULTIPOINT({})", points.join(","))
+            },
+            Geometry::MultiLineString(ref lines) => format!("MULTILINESTRING{}", wkt_rings(lines)),
+            Geometry::MultiPolygon(ref polygons) => {
+                let polygons: Vec<String> = polygons.iter().map(|p| wkt_rings(p)).collect();
+                format!("MULTIPOLYGON({})", polygons.join(","))
+            },
+            Geometry::Collection(ref geometries) => {
+                let geometries: Vec<String> = geometries.iter().map(|g| g.to_wkt()).collect();
+                format!("GEOMETRYCOLLECTION({})", geometries.join(","))
+            },
+        }
+    }
+
+    /// Converts the geometry to a GeoJSON geometry object.
+    pub fn to_geojson(&self) -> json::Value {
+        match *self {
+            Geometry::Point(Some((x, y))) => json::json!({"type": "Point", "coordinates": [x, y]}),
+            Geometry::Point(None) => json::json!({"type": "Point", "coordinates": []}),
+            Geometry::LineString(ref points) => json::json!({"type": "LineString", "coordinates": geojson_points(points)}),
+            Geometry::Polygon(ref rings) => json::json!({"type": "Polygon", "coordinates": geojson_rings(rings)}),
+            Geometry::MultiPoint(ref points) => json::json!({"type": "MultiPoint", "coordinates": geojson_points(points)}),
+            Geometry::MultiLineString(ref lines) => json::json!({"type": "MultiLineString", "coordinates": geojson_rings(lines)}),
+            Geometry::MultiPolygon(ref polygons) => {
+                let coordinates: json::Value = polygons.iter().map(|p| geojson_rings(p)).collect();
+                json::json!({"type": "MultiPolygon", "coordinates": coordinates})
+            },
+            Geometry::Collection(ref geometries) => {
+                let geometries: Vec<json::Value> = geometries.iter().map(|g| g.to_geojson()).collect();
+                json::json!({"type": "GeometryCollection", "geometries": geometries})
+            },
+        }
+    }
+}
@@ -0,0 +1,1803 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::str::{self, FromStr};
+
+use mysql::consts::{ColumnFlags, ColumnType};
+use serde_json as json;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::error::{Error, Result};
+use crate::value::{is_string, Converter, ValueOptions};
+
+
+/// Receives result sets and writes them out in some format.
+///
+/// A result set is announced with `begin`, followed by its rows and closed by
+/// `end`.  `tail` never ends its single result set and instead calls `flush`
+/// after each poll.  `finish` is called once after the last result set.
+pub trait RowWriter {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()>;
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()>;
+    fn end(&mut self) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    /// Writes a record telling that the output is alive at the given time
+    /// though there are no rows, for `tail --heartbeat`.  Only `json`, `csv`
+    /// and `tsv` output have one.
+    fn heartbeat(&mut self, _time: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    JsonColumns,
+    Markdown,
+    Table,
+    Html,
+    Xml,
+    Msgpack,
+    Parquet,
+    Arrow,
+    Ltsv,
+    Fixed,
+    Xlsx,
+    Vertical,
+    Template,
+    Raw,
+}
+
+/// Names accepted by `--format`, in the order they are listed in `--help`.
+/// A format is displayed by the first of its names.
+const FORMATS: &[(&str, Format)] = &[
+    ("csv", Format::Csv),
+    ("tsv", Format::Tsv),
+    ("json", Format::Json),
+    ("jsonl", Format::Json),
+    ("ndjson", Format::Json),
+    ("json-lines", Format::Json),
+    ("json-columns", Format::JsonColumns),
+    ("markdown", Format::Markdown),
+    ("table", Format::Table),
+    ("html", Format::Html),
+    ("xml", Format::Xml),
+    ("msgpack", Format::Msgpack),
+    ("parquet", Format::Parquet),
+    ("arrow", Format::Arrow),
+    ("ltsv", Format::Ltsv),
+    ("fixed", Format::Fixed),
+    ("xlsx", Format::Xlsx),
+    ("vertical", Format::Vertical),
+    ("template", Format::Template),
+    ("raw", Format::Raw),
+];
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Format, String> {
+        FORMATS.iter()
+            .find(|&&(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, format)| format)
+            .ok_or_else(|| format!("unknown format: {}", s))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let &(name, _) = FORMATS.iter().find(|&&(_, format)| format == *self).expect("every format has a name");
+        write!(f, "{}", name)
+    }
+}
+
+/// Settings shared by all writers.
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    /// How values are converted.
+    pub values: ValueOptions,
+    /// Omit the header row of `csv` and `tsv` output.
+    pub no_header: bool,
+    /// Repeat the header row of `csv` and `tsv` output every N rows.
+    pub header_every: Option<usize>,
+    /// Text written for NULL in `csv` and `tsv` output.
+    pub null_string: Option<String>,
+    /// End records of `csv` and `tsv` output with CRLF.
+    pub crlf: bool,
+    /// Start `csv` and `tsv` output with a UTF-8 byte-order mark.
+    pub bom: bool,
+    /// Field delimiter of `csv` output.
+    pub delimiter: Option<u8>,
+    /// When fields of `csv` output are quoted.
+    pub quote_style: Option<csv::QuoteStyle>,
+    /// Quote character of `csv` output.
+    pub quote_char: Option<u8>,
+    /// Character escaping quotes in `csv` output, instead of doubling them.
+    pub escape_char: Option<u8>,
+    /// Terminate records of `csv`, `tsv` and `json` output with NUL instead of
+    /// a newline.
+    pub null_terminated: bool,
+    /// Wrap each result set of `json` output in an object with column metadata.
+    pub json_envelope: bool,
+    /// Maximum number of rows per object in `json-columns` output.
+    pub chunk_rows: Option<usize>,
+    /// Display width beyond which `table` cells are truncated.
+    pub max_column_width: Option<usize>,
+    /// Wrap `html` output in a complete document.
+    pub html_full_page: bool,
+    /// Number of rows per Parquet row group.
+    #[cfg_attr(not(feature = "parquet-output"), allow(dead_code))]
+    pub row_group_size: usize,
+    /// Explicit column widths for `fixed` output.
+    pub widths: Vec<usize>,
+    /// Number of rows `fixed` output examines to compute column widths.
+    pub width_sample: usize,
+    /// Line layout for `template` output.
+    pub template: Option<Template>,
+    /// Bytes written after each value in `raw` output.
+    pub raw_separator: String,
+    /// Number of rows per Arrow record batch.
+    #[cfg_attr(not(feature = "arrow-output"), allow(dead_code))]
+    pub record_batch_size: usize,
+    /// Only write these columns, in this order.
+    pub columns: Vec<String>,
+    /// Start of heartbeat lines in `csv` and `tsv` output.
+    pub heartbeat_prefix: String,
+}
+
+impl OutputOptions {
+    /// Byte ending each record of line-oriented output.
+    fn terminator(&self) -> u8 {
+        if self.null_terminated { b'\0' } else { b'\n' }
+    }
+}
+
+impl Format {
+    pub fn names() -> Vec<&'static str> {
+        FORMATS.iter().map(|&(name, _)| name).collect()
+    }
+
+    /// Whether rows can be written as soon as they arrive.  Formats which need
+    /// to see the whole result set first cannot be used with `tail`.
+    pub fn is_streaming(self) -> bool {
+        !matches!(self, Format::JsonColumns | Format::Markdown | Format::Table | Format::Parquet | Format::Xlsx)
+    }
+
+    /// Whether every row names its fields, so that rows with other columns
+    /// can follow.
+    pub fn names_fields(self) -> bool {
+        matches!(self, Format::Json | Format::Msgpack | Format::Ltsv)
+    }
+
+    /// Whether the format has heartbeat records.
+    pub fn has_heartbeat(self) -> bool {
+        matches!(self, Format::Json | Format::Csv | Format::Tsv)
+    }
+
+    /// Whether a statement without a result set is written by default as a
+    /// row telling what it did.  Formats which fail on, or give a file or
+    /// sheet of its own to, a result set with other columns leave it out.
+    pub fn writes_summaries(self) -> bool {
+        !matches!(self, Format::Raw | Format::Template | Format::Parquet | Format::Arrow | Format::Xlsx)
+    }
+
+    /// Whether the format can only be written to a regular file.
+    pub fn requires_file(self) -> bool {
+        matches!(self, Format::Parquet | Format::Xlsx)
+    }
+
+    pub fn writer<'a, W: Write + Send + 'a>(self, out: W, opts: &OutputOptions) -> Result<Box<dyn RowWriter + 'a>> {
+        let writer = self.format_writer(out, opts)?;
+        Ok(if opts.columns.is_empty() { writer } else { Box::new(SelectWriter::new(writer, opts.columns.clone())) })
+    }
+
+    fn format_writer<'a, W: Write + Send + 'a>(self, out: W, opts: &OutputOptions) -> Result<Box<dyn RowWriter + 'a>> {
+        let conv = Converter::new(opts.values.clone());
+        Ok(match self {
+            Format::Csv | Format::Tsv => Box::new(CsvWriter::new(self, out, conv, opts)),
+            Format::Json if opts.json_envelope => Box::new(JsonEnvelopeWriter::new(out, conv, opts.terminator())),
+            Format::Json => Box::new(JsonWriter::new(out, conv, opts.terminator())),
+            Format::JsonColumns => Box::new(JsonColumnsWriter::new(out, conv, opts.chunk_rows)),
+            Format::Markdown => Box::new(MarkdownWriter::new(out, conv)),
+            Format::Table => Box::new(TableWriter::new(out, conv, opts.max_column_width)),
+            Format::Html => Box::new(HtmlWriter::new(out, conv, opts.html_full_page)),
+            Format::Xml => Box::new(XmlWriter::new(out, conv)),
+            Format::Msgpack => Box::new(MsgpackWriter::new(out, conv)),
+            Format::Ltsv => Box::new(LtsvWriter::new(out, conv)),
+            Format::Template => {
+                let template = opts.template.clone().ok_or_else(|| Error::Usage("--format template requires --template".to_owned()))?;
+                Box::new(TemplateWriter::new(out, conv, template))
+            },
+            Format::Vertical => Box::new(VerticalWriter::new(out, conv)),
+            Format::Raw => Box::new(RawWriter::new(out, conv, opts.raw_separator.clone())),
+            Format::Fixed => Box::new(FixedWriter::new(out, conv, opts.widths.clone(), opts.width_sample)),
+            #[cfg(feature = "parquet-output")]
+            Format::Parquet => Box::new(crate::columnar::ParquetWriter::new(out, conv, opts.row_group_size)),
+            #[cfg(not(feature = "parquet-output"))]
+            Format::Parquet => return Err(unsupported("parquet", "parquet-output")),
+            #[cfg(feature = "arrow-output")]
+            Format::Arrow => Box::new(crate::columnar::ArrowStreamWriter::new(out, conv, opts.record_batch_size)),
+            #[cfg(not(feature = "arrow-output"))]
+            Format::Arrow => return Err(unsupported("arrow", "arrow-output")),
+            #[cfg(feature = "xlsx-output")]
+            Format::Xlsx => Box::new(crate::xlsx::XlsxWriter::new(out, conv)),
+            #[cfg(not(feature = "xlsx-output"))]
+            Format::Xlsx => return Err(unsupported("xlsx", "xlsx-output")),
+        })
+    }
+}
+
+/// Passes on only the columns asked for by `--columns`.
+struct SelectWriter<'a> {
+    inner: Box<dyn RowWriter + 'a>,
+    names: Vec<String>,
+    indices: Vec<usize>,
+    columns: Arc<Vec<mysql::Column>>,
+}
+
+impl<'a> SelectWriter<'a> {
+    fn new(inner: Box<dyn RowWriter + 'a>, names: Vec<String>) -> SelectWriter<'a> {
+        SelectWriter { inner, names, indices: Vec::new(), columns: Arc::new(Vec::new()) }
+    }
+}
+
+impl<'a> RowWriter for SelectWriter<'a> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.indices = self.names.iter().map(|name| {
+            columns.iter().position(|c| c.name_str().eq_ignore_ascii_case(name)).ok_or_else(|| {
+                let available: Vec<String> = columns.iter().map(|c| c.name_str().into_owned()).collect();
+                Error::Usage(format!("unknown column in --columns: {} (available: {})", name, available.join(", ")))
+            })
+        }).collect::<Result<_>>()?;
+        self.columns = Arc::new(self.indices.iter().map(|&i| columns[i].clone()).collect());
+        self.inner.begin(&self.columns)
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let values = self.indices.iter().map(|&i| row[i].clone()).collect();
+        self.inner.write_row(&mysql_common::row::new_row(values, self.columns.clone()))
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.inner.end()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+
+    fn heartbeat(&mut self, time: &str) -> Result<()> {
+        self.inner.heartbeat(time)
+    }
+}
+
+#[cfg(not(all(feature = "parquet-output", feature = "arrow-output", feature = "xlsx-output")))]
+fn unsupported(format: &str, feature: &str) -> Error {
+    Error::Usage(format!("--format {} is not available in this build (rebuild with `--features {}`)", format, feature))
+}
+
+/// Footer closing a result set in the `mysql` client.
+fn rows_in_set(rows: usize) -> String {
+    if rows == 1 {
+        "1 row in set\n\n".to_owned()
+    }
+    else {
+        format!("{} rows in set\n\n", rows)
+    }
+}
+
+/// Cuts `s` down to `max` display columns, marking the cut with an ellipsis
+/// if there is room for one.
+fn truncate_width(s: String, max: usize) -> String {
+    if s.width() <= max {
+        return s;
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max {
+            break;
+        }
+        truncated.push(c);
+        width += w;
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// A column definition as the server would send it, for values made up here
+/// rather than read.
+pub fn make_column(name: &str, column_type: ColumnType, flags: ColumnFlags, charset: u16, length: u32, decimals: u8) -> mysql::Column {
+    let mut payload = Vec::new();
+    for s in &["def", "", "", "", name, name] {
+        payload.push(s.len() as u8);
+        payload.extend_from_slice(s.as_bytes());
+    }
+    payload.push(0x0c);
+    payload.extend_from_slice(&charset.to_le_bytes());
+    payload.extend_from_slice(&length.to_le_bytes());
+    payload.push(column_type as u8);
+    payload.extend_from_slice(&flags.bits().to_le_bytes());
+    payload.extend_from_slice(&[decimals, 0, 0]);
+    mysql_common::packets::column_from_payload(payload).expect("a column definition is well-formed")
+}
+
+fn column_names(columns: &[mysql::Column]) -> Vec<String> {
+    columns.iter().map(|c| c.name_str().into_owned()).collect()
+}
+
+fn row_object(column_names: &[String], row: &mysql::Row, values: &Converter) -> Result<json::Map<String, json::Value>> {
+    column_names.iter().enumerate().map(|(i, col_name)| {
+        Ok((col_name.to_owned(), values.json(i, &row[i])?))
+    }).collect()
+}
+
+/// Escapes a value for TSV output the same way `mysql --batch` does, so that
+/// embedded tabs and newlines cannot break the record structure.
+fn escape_tsv(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A writer whose clones write to the same stream.
+struct SharedWriter<W: Write>(Rc<RefCell<W>>);
+
+impl<W: Write> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter(self.0.clone())
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// A writer which counts the bytes written through it.
+pub struct Counted<W: Write> {
+    out: W,
+    count: Arc<AtomicU64>,
+}
+
+impl<W: Write> Counted<W> {
+    pub fn new(out: W, count: Arc<AtomicU64>) -> Counted<W> {
+        Counted { out, count }
+    }
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+struct CsvWriter<W: Write> {
+    wtr: csv::Writer<SharedWriter<W>>,
+    /// The stream `wtr` writes to, for records it cannot write itself.
+    out: SharedWriter<W>,
+    tsv: bool,
+    conv: Converter,
+    header: Option<Vec<String>>,
+    header_every: Option<usize>,
+    rows: usize,
+    null_string: Option<String>,
+    /// Whether a byte-order mark is still to be written.
+    bom: bool,
+    /// Copy of the writer's configuration, used to write records in which a
+    /// value equal to `null_string` has to be quoted.
+    dialect: Option<csv_core::Writer>,
+    heartbeat_prefix: String,
+    terminator: &'static [u8],
+}
+
+impl<W: Write> CsvWriter<W> {
+    fn new(format: Format, out: W, conv: Converter, opts: &OutputOptions) -> CsvWriter<W> {
+        let tsv = format == Format::Tsv;
+        let mut builder = csv::WriterBuilder::new();
+        let mut dialect = csv_core::WriterBuilder::new();
+        // Consecutive result sets may differ in the number of columns.
+        builder.flexible(true);
+        if opts.null_terminated {
+            builder.terminator(csv::Terminator::Any(opts.terminator()));
+            dialect.terminator(csv_core::Terminator::Any(opts.terminator()));
+        }
+        else if opts.crlf {
+            builder.terminator(csv::Terminator::CRLF);
+            dialect.terminator(csv_core::Terminator::CRLF);
+        }
+        if let Some(delimiter) = opts.delimiter {
+            builder.delimiter(delimiter);
+            dialect.delimiter(delimiter);
+        }
+        if let Some(style) = opts.quote_style {
+            builder.quote_style(style);
+            dialect.quote_style(match style {
+                csv::QuoteStyle::Always => csv_core::QuoteStyle::Always,
+                csv::QuoteStyle::Never => csv_core::QuoteStyle::Never,
+                csv::QuoteStyle::NonNumeric => csv_core::QuoteStyle::NonNumeric,
+                _ => csv_core::QuoteStyle::Necessary,
+            });
+        }
+        if let Some(quote) = opts.quote_char {
+            builder.quote(quote);
+            dialect.quote(quote);
+        }
+        if let Some(escape) = opts.escape_char {
+            builder.escape(escape)
+                   .double_quote(false);
+            dialect.escape(escape)
+                   .double_quote(false);
+        }
+        if tsv {
+            builder.delimiter(b'\t')
+                   .quote_style(csv::QuoteStyle::Never);
+        }
+        let out = SharedWriter(Rc::new(RefCell::new(out)));
+        CsvWriter {
+            wtr: builder.from_writer(out.clone()),
+            out,
+            tsv,
+            conv,
+            header: if opts.no_header { None } else { Some(Vec::new()) },
+            header_every: opts.header_every,
+            rows: 0,
+            null_string: opts.null_string.clone(),
+            bom: opts.bom,
+            // Escaping already keeps TSV values apart from the NULL string.
+            dialect: if tsv || opts.null_string.is_none() { None } else { Some(dialect.build()) },
+            heartbeat_prefix: opts.heartbeat_prefix.clone(),
+            terminator: if opts.null_terminated { b"\0" } else if opts.crlf { b"\r\n" } else { b"\n" },
+        }
+    }
+
+    /// Writes a record, quoting the fields marked in `forced` regardless of
+    /// the quote style, which the csv writer cannot do by itself.
+    fn write_quoted_record(&mut self, values: &[String], forced: &[bool]) -> Result<()> {
+        let dialect = self.dialect.as_ref().expect("CSV dialect is configured");
+        let quote = dialect.get_quote();
+        let mut record = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                record.push(dialect.get_delimiter());
+            }
+            let input = value.as_bytes();
+            if forced[i] || dialect.should_quote(input) {
+                // Escaping at most doubles the length of a value.
+                let mut buf = vec![0; input.len() * 2];
+                let (_, _, n) = csv_core::quote(input, &mut buf, quote, dialect.get_escape(), dialect.get_double_quote());
+                record.push(quote);
+                record.extend_from_slice(&buf[..n]);
+                record.push(quote);
+            }
+            else {
+                record.extend_from_slice(input);
+            }
+        }
+        match dialect.get_terminator() {
+            csv_core::Terminator::Any(b) => record.push(b),
+            _ => record.extend_from_slice(b"\r\n"),
+        }
+        self.wtr.flush()?;
+        self.out.write_all(&record)?;
+        Ok(())
+    }
+
+    fn field(&self, s: String) -> String {
+        if self.tsv {
+            escape_tsv(&s)
+        }
+        else {
+            s
+        }
+    }
+}
+
+impl<W: Write> RowWriter for CsvWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.rows = 0;
+        if self.bom && !columns.is_empty() {
+            self.out.write_all("\u{feff}".as_bytes())?;
+            self.bom = false;
+        }
+        if self.header.is_some() {
+            let header: Vec<String> = column_names(columns).into_iter().map(|s| self.field(s)).collect();
+            if !header.is_empty() {
+                self.wtr.write_record(&header)?;
+            }
+            self.header = Some(header);
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        if let (Some(ref header), Some(every)) = (&self.header, self.header_every) {
+            if self.rows > 0 && self.rows.is_multiple_of(every) {
+                self.wtr.write_record(header)?;
+            }
+        }
+        let mut forced = Vec::new();
+        let values: Vec<String> = (0..row.len()).map(|i| {
+            match (&row[i], &self.null_string) {
+                (&mysql::Value::NULL, Some(null)) => {
+                    forced.push(false);
+                    Ok(null.clone())
+                },
+                (val, null) => {
+                    let s = self.field(self.conv.text(i, val)?);
+                    forced.push(self.dialect.is_some() && null.as_ref() == Some(&s));
+                    Ok(s)
+                },
+            }
+        }).collect::<Result<_>>()?;
+        if forced.contains(&true) {
+            self.write_quoted_record(&values, &forced)?;
+        }
+        else {
+            self.wtr.write_record(&values)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+
+    /// Writes `# heartbeat 2024-05-01T12:00:00Z` with the default prefix.
+    fn heartbeat(&mut self, time: &str) -> Result<()> {
+        self.wtr.flush()?;
+        write!(self.out, "{} heartbeat {}", self.heartbeat_prefix, time)?;
+        self.out.write_all(self.terminator)?;
+        Ok(())
+    }
+}
+
+struct JsonWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    terminator: u8,
+    column_names: Vec<String>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    fn new(out: W, conv: Converter, terminator: u8) -> JsonWriter<W> {
+        JsonWriter {
+            out,
+            conv,
+            terminator,
+            column_names: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowWriter for JsonWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.column_names = column_names(columns);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let row_obj = row_object(&self.column_names, row, &self.conv)?;
+        json::to_writer(&mut self.out, &row_obj)?;
+        self.out.write_all(&[self.terminator])?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Writes `{"_heartbeat":"2024-05-01T12:00:00Z"}`.
+    fn heartbeat(&mut self, time: &str) -> Result<()> {
+        json::to_writer(&mut self.out, &json::json!({ "_heartbeat": time }))?;
+        self.out.write_all(&[self.terminator])?;
+        Ok(())
+    }
+}
+
+/// Name of a column type as the server calls it, e.g. `LONG` or `VAR_STRING`.
+fn type_name(column: &mysql::Column) -> String {
+    let name = format!("{:?}", column.column_type());
+    name.trim_start_matches("MYSQL_TYPE_").to_owned()
+}
+
+/// Writes each result set as an object holding the column metadata and the
+/// rows as positional arrays:
+///
+/// ```text
+/// {"columns":[{"name":"id","type":"LONG","nullable":false}],"rows":[[1],[2]]}
+/// ```
+///
+/// The object is written piece by piece, so rows are not held in memory.
+struct JsonEnvelopeWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    terminator: u8,
+    in_result: bool,
+    rows: usize,
+}
+
+impl<W: Write> JsonEnvelopeWriter<W> {
+    fn new(out: W, conv: Converter, terminator: u8) -> JsonEnvelopeWriter<W> {
+        JsonEnvelopeWriter {
+            out,
+            conv,
+            terminator,
+            in_result: false,
+            rows: 0,
+        }
+    }
+}
+
+impl<W: Write> RowWriter for JsonEnvelopeWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<json::Value> = columns.iter().map(|c| {
+            json::json!({
+                "name": c.name_str(),
+                "type": type_name(c),
+                "nullable": !c.flags().contains(ColumnFlags::NOT_NULL_FLAG),
+            })
+        }).collect();
+        self.out.write_all(b"{\"columns\":")?;
+        json::to_writer(&mut self.out, &columns)?;
+        self.out.write_all(b",\"rows\":[")?;
+        self.in_result = true;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        if self.rows > 0 {
+            self.out.write_all(b",")?;
+        }
+        let values: Vec<json::Value> = (0..row.len()).map(|i| self.conv.json(i, &row[i])).collect::<Result<_>>()?;
+        json::to_writer(&mut self.out, &values)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.in_result {
+            self.out.write_all(b"]}")?;
+            self.out.write_all(&[self.terminator])?;
+            self.in_result = false;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes each result set as a single JSON object mapping column names to
+/// arrays of values.
+///
+/// The arrays are kept in memory until the result set ends, or until
+/// `chunk_rows` rows have been collected, at which point the chunk is written
+/// as an object on its own line.
+struct JsonColumnsWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    chunk_rows: Option<usize>,
+    column_names: Vec<String>,
+    values: Vec<Vec<json::Value>>,
+    rows: usize,
+    chunks: usize,
+}
+
+impl<W: Write> JsonColumnsWriter<W> {
+    fn new(out: W, conv: Converter, chunk_rows: Option<usize>) -> JsonColumnsWriter<W> {
+        JsonColumnsWriter {
+            out,
+            conv,
+            chunk_rows,
+            column_names: Vec::new(),
+            values: Vec::new(),
+            rows: 0,
+            chunks: 0,
+        }
+    }
+
+    fn write_chunk(&mut self) -> Result<()> {
+        let values = self.values.iter_mut().map(|v| json::Value::Array(std::mem::take(v)));
+        let obj: json::Map<String, json::Value> = self.column_names.iter().cloned().zip(values).collect();
+        json::to_writer(&mut self.out, &obj)?;
+        self.out.write_all(b"\n")?;
+        self.rows = 0;
+        self.chunks += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for JsonColumnsWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.column_names = column_names(columns);
+        self.values = vec![Vec::new(); columns.len()];
+        self.rows = 0;
+        self.chunks = 0;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        for (i, values) in self.values.iter_mut().enumerate() {
+            values.push(self.conv.json(i, &row[i])?);
+        }
+        self.rows += 1;
+        if self.chunk_rows.is_some_and(|n| self.rows >= n) {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        // An empty result set still gets an object, but a chunk which would
+        // only repeat the column names does not.
+        if !self.column_names.is_empty() && (self.rows > 0 || self.chunks == 0) {
+            self.write_chunk()?;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes each row as a line of `label:value` pairs separated by tabs.
+struct LtsvWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    labels: Vec<String>,
+}
+
+impl<W: Write> LtsvWriter<W> {
+    fn new(out: W, conv: Converter) -> LtsvWriter<W> {
+        LtsvWriter {
+            out,
+            conv,
+            labels: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowWriter for LtsvWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        let labels = column_names(columns);
+        if let Some(label) = labels.iter().find(|l| l.is_empty() || l.contains([':', '\t', '\n', '\r'])) {
+            return Err(Error::Value(format!("column name {:?} cannot be used as an LTSV label; rename it with AS", label)));
+        }
+        self.labels = labels;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let mut line = String::new();
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                line.push('\t');
+            }
+            line.push_str(label);
+            line.push(':');
+            line.push_str(&escape_tsv(&self.conv.text(i, &row[i])?));
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes each row as a MessagePack map.  Maps are simply concatenated, which
+/// streaming MessagePack readers handle without any framing.
+struct MsgpackWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    column_names: Vec<String>,
+}
+
+impl<W: Write> MsgpackWriter<W> {
+    fn new(out: W, conv: Converter) -> MsgpackWriter<W> {
+        MsgpackWriter {
+            out,
+            conv,
+            column_names: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowWriter for MsgpackWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.column_names = column_names(columns);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let row_obj = row_object(&self.column_names, row, &self.conv)?;
+        rmp_serde::encode::write_named(&mut self.out, &row_obj)?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Renders each result set as a GitHub-flavored Markdown table.
+///
+/// Columns are padded to a common width, so every row of a result set is kept
+/// in memory until the result set ends.  Avoid it for very large results.
+struct MarkdownWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    tables: usize,
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    fn new(out: W, conv: Converter) -> MarkdownWriter<W> {
+        MarkdownWriter {
+            out,
+            conv,
+            header: Vec::new(),
+            rows: Vec::new(),
+            tables: 0,
+        }
+    }
+
+    fn escape(s: &str) -> String {
+        // A raw newline would end the table row, so use an inline break.
+        s.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+    }
+
+    fn write_line(&mut self, cells: &[String], widths: &[usize]) -> Result<()> {
+        let mut line = String::from("|");
+        for (cell, width) in cells.iter().zip(widths) {
+            line.push(' ');
+            line.push_str(cell);
+            line.extend(std::iter::repeat_n(' ', width - cell.chars().count()));
+            line.push_str(" |");
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for MarkdownWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.header = column_names(columns).iter().map(|s| Self::escape(s)).collect();
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let cells = (0..row.len()).map(|i| Ok(Self::escape(&self.conv.text(i, &row[i])?))).collect::<Result<_>>()?;
+        self.rows.push(cells);
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.header.is_empty() {
+            return Ok(());
+        }
+        // The delimiter row needs at least three dashes per column.
+        let mut widths: Vec<usize> = self.header.iter().map(|s| s.chars().count().max(3)).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let header = std::mem::take(&mut self.header);
+        let rows = std::mem::take(&mut self.rows);
+        // Adjacent tables would otherwise be parsed as one.
+        if self.tables > 0 {
+            self.out.write_all(b"\n")?;
+        }
+        self.tables += 1;
+        self.write_line(&header, &widths)?;
+        let delimiter: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+        self.write_line(&delimiter, &widths)?;
+        for row in &rows {
+            self.write_line(row, &widths)?;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Draws each result set as a box like the interactive `mysql` client does.
+///
+/// Like `markdown`, the whole result set is buffered to compute the column
+/// widths.
+struct TableWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    max_column_width: Option<usize>,
+    header: Vec<String>,
+    right_aligned: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+impl<W: Write> TableWriter<W> {
+    fn new(out: W, conv: Converter, max_column_width: Option<usize>) -> TableWriter<W> {
+        TableWriter {
+            out,
+            conv,
+            max_column_width,
+            header: Vec::new(),
+            right_aligned: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Whether the `mysql` client would right-align values of the column.
+    fn is_numeric(column: &mysql::Column) -> bool {
+        use ColumnType::*;
+        matches!(column.column_type(),
+                 MYSQL_TYPE_DECIMAL | MYSQL_TYPE_NEWDECIMAL | MYSQL_TYPE_TINY | MYSQL_TYPE_SHORT |
+                 MYSQL_TYPE_LONG | MYSQL_TYPE_INT24 | MYSQL_TYPE_LONGLONG | MYSQL_TYPE_FLOAT |
+                 MYSQL_TYPE_DOUBLE | MYSQL_TYPE_YEAR)
+    }
+
+    fn truncate(&self, s: String) -> String {
+        match self.max_column_width {
+            Some(max) => truncate_width(s, max),
+            None => s,
+        }
+    }
+
+    fn write_separator(&mut self, widths: &[usize]) -> Result<()> {
+        let mut line = String::from("+");
+        for &width in widths {
+            line.push_str(&"-".repeat(width + 2));
+            line.push('+');
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_line(&mut self, cells: &[String], widths: &[usize], right_aligned: &[bool]) -> Result<()> {
+        let mut line = String::from("|");
+        for ((cell, &width), &right) in cells.iter().zip(widths).zip(right_aligned) {
+            let padding = " ".repeat(width - cell.width());
+            line.push(' ');
+            if right {
+                line.push_str(&padding);
+                line.push_str(cell);
+            }
+            else {
+                line.push_str(cell);
+                line.push_str(&padding);
+            }
+            line.push_str(" |");
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for TableWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.header = column_names(columns).into_iter().map(|s| self.truncate(s)).collect();
+        self.right_aligned = columns.iter().map(Self::is_numeric).collect();
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let cells = (0..row.len()).map(|i| {
+            match row[i] {
+                mysql::Value::NULL => Ok("NULL".to_owned()),
+                ref val => Ok(self.truncate(self.conv.text(i, val)?)),
+            }
+        }).collect::<Result<_>>()?;
+        self.rows.push(cells);
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.header.is_empty() {
+            return Ok(());
+        }
+        let header = std::mem::take(&mut self.header);
+        let right_aligned = std::mem::take(&mut self.right_aligned);
+        let rows = std::mem::take(&mut self.rows);
+        if rows.is_empty() {
+            self.out.write_all(b"Empty set\n\n")?;
+            return self.flush();
+        }
+
+        let mut widths: Vec<usize> = header.iter().map(|s| s.width()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+        self.write_separator(&widths)?;
+        self.write_line(&header, &widths, &vec![false; header.len()])?;
+        self.write_separator(&widths)?;
+        for row in &rows {
+            self.write_line(row, &widths, &right_aligned)?;
+        }
+        self.write_separator(&widths)?;
+        self.out.write_all(rows_in_set(rows.len()).as_bytes())?;
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+const HTML_PROLOGUE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<style>
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: left; }
+th { background: #eee; }
+td.null { background: #f8f8f8; }
+</style>
+</head>
+<body>
+"#;
+
+const HTML_EPILOGUE: &str = "</body>\n</html>\n";
+
+/// Escapes text for use in HTML or XML content and attribute values.
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes each result set as an HTML `<table>`, one `<tr>` per row.
+struct HtmlWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    full_page: bool,
+    started: bool,
+    in_table: bool,
+}
+
+impl<W: Write> HtmlWriter<W> {
+    fn new(out: W, conv: Converter, full_page: bool) -> HtmlWriter<W> {
+        HtmlWriter {
+            out,
+            conv,
+            full_page,
+            started: false,
+            in_table: false,
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            if self.full_page {
+                self.out.write_all(HTML_PROLOGUE.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for HtmlWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.start()?;
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let mut html = String::from("<table>\n<thead>\n<tr>");
+        for name in column_names(columns) {
+            html.push_str("<th>");
+            html.push_str(&escape_html(&name));
+            html.push_str("</th>");
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+        self.out.write_all(html.as_bytes())?;
+        self.in_table = true;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let mut html = String::from("<tr>");
+        for i in 0..row.len() {
+            match row[i] {
+                mysql::Value::NULL => html.push_str(r#"<td class="null"></td>"#),
+                ref val => {
+                    html.push_str("<td>");
+                    html.push_str(&escape_html(&self.conv.text(i, val)?));
+                    html.push_str("</td>");
+                },
+            }
+        }
+        html.push_str("</tr>\n");
+        self.out.write_all(html.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.in_table {
+            self.out.write_all(b"</tbody>\n</table>\n")?;
+            self.in_table = false;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.start()?;
+        if self.full_page {
+            self.out.write_all(HTML_EPILOGUE.as_bytes())?;
+        }
+        self.flush()
+    }
+}
+
+/// Writes rows in the layout of `mysql --xml`, one `<resultset>` element per
+/// result set.  Rows are written as they arrive.
+struct XmlWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    column_names: Vec<String>,
+    started: bool,
+    in_resultset: bool,
+}
+
+impl<W: Write> XmlWriter<W> {
+    fn new(out: W, conv: Converter) -> XmlWriter<W> {
+        XmlWriter {
+            out,
+            conv,
+            column_names: Vec::new(),
+            started: false,
+            in_resultset: false,
+        }
+    }
+}
+
+impl<W: Write> RowWriter for XmlWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        if !self.started {
+            self.out.write_all(b"<?xml version=\"1.0\"?>\n\n")?;
+            self.started = true;
+        }
+        self.column_names = column_names(columns).iter().map(|s| escape_html(s)).collect();
+        if !columns.is_empty() {
+            self.out.write_all(b"<resultset xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n")?;
+            self.in_resultset = true;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let mut xml = String::from("  <row>\n");
+        for (i, name) in self.column_names.iter().enumerate() {
+            match row[i] {
+                mysql::Value::NULL => {
+                    xml.push_str(&format!("\t<field name=\"{}\" xsi:nil=\"true\" />\n", name));
+                },
+                ref val => {
+                    let encoding = self.conv.encoding(i, val).map_or_else(String::new, |encoding| format!(" encoding=\"{}\"", encoding));
+                    xml.push_str(&format!("\t<field name=\"{}\"{}>{}</field>\n", name, encoding, escape_html(&self.conv.text(i, val)?)));
+                },
+            }
+        }
+        xml.push_str("  </row>\n");
+        self.out.write_all(xml.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.in_resultset {
+            self.out.write_all(b"</resultset>\n")?;
+            self.in_resultset = false;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Pads every column to a fixed display width, truncating longer values.
+///
+/// Unless widths are given explicitly, the first `sample` rows of a result set
+/// are held back to compute them from the widest value of each column.
+struct FixedWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    explicit_widths: Vec<usize>,
+    sample: usize,
+    header: Vec<String>,
+    widths: Option<Vec<usize>>,
+    pending: Vec<Vec<String>>,
+}
+
+impl<W: Write> FixedWriter<W> {
+    fn new(out: W, conv: Converter, explicit_widths: Vec<usize>, sample: usize) -> FixedWriter<W> {
+        FixedWriter {
+            out,
+            conv,
+            explicit_widths,
+            sample,
+            header: Vec::new(),
+            widths: None,
+            pending: Vec::new(),
+        }
+    }
+
+    fn write_line(&mut self, cells: &[String]) -> Result<()> {
+        let widths = self.widths.as_ref().expect("widths are known before writing");
+        let mut line = String::new();
+        for (i, (cell, &width)) in cells.iter().zip(widths).enumerate() {
+            if i > 0 {
+                line.push(' ');
+            }
+            let cell = truncate_width(cell.replace(['\r', '\n'], " "), width);
+            let padding = width.saturating_sub(cell.width());
+            line.push_str(&cell);
+            line.push_str(&" ".repeat(padding));
+        }
+        let mut line = line.trim_end_matches(' ').to_owned();
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Fixes the column widths and writes the header and any held back rows.
+    fn start(&mut self) -> Result<()> {
+        let widths = if self.explicit_widths.is_empty() {
+            let mut widths: Vec<usize> = self.header.iter().map(|s| s.width()).collect();
+            for row in &self.pending {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.width());
+                }
+            }
+            widths
+        }
+        else {
+            self.explicit_widths.clone()
+        };
+        let underline = widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>();
+        self.widths = Some(widths);
+        let header = std::mem::take(&mut self.header);
+        self.write_line(&header)?;
+        self.write_line(&underline)?;
+        for row in std::mem::take(&mut self.pending) {
+            self.write_line(&row)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for FixedWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        if !self.explicit_widths.is_empty() && !columns.is_empty() && self.explicit_widths.len() != columns.len() {
+            return Err(Error::Usage(format!("--widths gives {} widths but the result has {} columns",
+                                            self.explicit_widths.len(), columns.len())));
+        }
+        self.header = column_names(columns);
+        self.widths = None;
+        self.pending.clear();
+        if !columns.is_empty() && !self.explicit_widths.is_empty() {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let cells: Vec<String> = (0..row.len()).map(|i| self.conv.text(i, &row[i])).collect::<Result<_>>()?;
+        if self.widths.is_some() {
+            self.write_line(&cells)
+        }
+        else {
+            self.pending.push(cells);
+            if self.pending.len() >= self.sample {
+                self.start()?;
+            }
+            Ok(())
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.widths.is_none() && !self.header.is_empty() {
+            self.start()?;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes each row as a block of `column: value` lines, as the `mysql` client
+/// does for statements terminated with `\G`.
+struct VerticalWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    labels: Vec<String>,
+    rows: usize,
+}
+
+impl<W: Write> VerticalWriter<W> {
+    fn new(out: W, conv: Converter) -> VerticalWriter<W> {
+        VerticalWriter {
+            out,
+            conv,
+            labels: Vec::new(),
+            rows: 0,
+        }
+    }
+}
+
+impl<W: Write> RowWriter for VerticalWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        let names = column_names(columns);
+        let width = names.iter().map(|s| s.width()).max().unwrap_or(0);
+        self.labels = names.into_iter().map(|s| format!("{}{}", " ".repeat(width - s.width()), s)).collect();
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        self.rows += 1;
+        let mut block = format!("{} {}. row {}\n", "*".repeat(27), self.rows, "*".repeat(27));
+        for (i, label) in self.labels.iter().enumerate() {
+            let value = match row[i] {
+                mysql::Value::NULL => "NULL".to_owned(),
+                ref val => self.conv.text(i, val)?,
+            };
+            block.push_str(&format!("{}: {}\n", label, value));
+        }
+        self.out.write_all(block.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if !self.labels.is_empty() {
+            let footer = if self.rows == 0 { "Empty set\n\n".to_owned() } else { rows_in_set(self.rows) };
+            self.out.write_all(footer.as_bytes())?;
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Character denoted by the escape sequence `\c`, if it is one of `\t`,
+/// `\n`, `\r`, `\0` and `\\`.
+fn unescape_char(c: char) -> Option<char> {
+    match c {
+        't' => Some('\t'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        _ => None,
+    }
+}
+
+/// Names accepted by `--quote-style`.
+pub const QUOTE_STYLES: &[&str] = &["always", "necessary", "never", "non-numeric"];
+
+pub fn parse_quote_style(s: &str) -> std::result::Result<csv::QuoteStyle, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "always" => Ok(csv::QuoteStyle::Always),
+        "necessary" => Ok(csv::QuoteStyle::Necessary),
+        "never" => Ok(csv::QuoteStyle::Never),
+        "non-numeric" => Ok(csv::QuoteStyle::NonNumeric),
+        _ => Err(format!("unknown quote style: {}", s)),
+    }
+}
+
+/// Parses a command line argument naming a single byte: an ASCII character or
+/// one of the escapes `\t`, `\n`, `\r`, `\0` and `\\`.
+pub fn parse_byte(option: &str, s: &str) -> Result<u8> {
+    let unescaped = unescape(s);
+    match unescaped.as_bytes() {
+        [b] if b.is_ascii() => Ok(*b),
+        _ => Err(Error::Usage(format!(
+            "invalid value for {}: '{}' (expected a single ASCII character or one of \\t, \\n, \\r, \\0, \\\\)",
+            option, s))),
+    }
+}
+
+/// Interprets `\t`, `\n`, `\r`, `\0` and `\\` escapes in a command line
+/// argument.  Other backslashes are kept as they are.
+pub fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek().cloned().and_then(unescape_char) {
+            Some(e) if c == '\\' => {
+                unescaped.push(e);
+                chars.next();
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Writes the values of a single-column result, each followed by a
+/// separator.  Strings, binary ones included, are written verbatim; other
+/// values as the text other formats give them.
+struct RawWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    separator: String,
+    /// Whether the column is a string one, whose bytes are written as they
+    /// are rather than converted.
+    verbatim: bool,
+}
+
+impl<W: Write> RawWriter<W> {
+    fn new(out: W, conv: Converter, separator: String) -> RawWriter<W> {
+        RawWriter {
+            out,
+            conv,
+            separator,
+            verbatim: false,
+        }
+    }
+}
+
+impl<W: Write> RowWriter for RawWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        if columns.len() > 1 {
+            return Err(Error::Usage(format!("--format raw requires a single column, but the result has {}", columns.len())));
+        }
+        self.verbatim = columns.first().is_some_and(is_string);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        match row[0] {
+            mysql::Value::NULL => {},
+            mysql::Value::Bytes(ref bytes) if self.verbatim => self.out.write_all(bytes)?,
+            ref val => self.out.write_all(self.conv.text(0, val)?.as_bytes())?,
+        }
+        self.out.write_all(self.separator.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Literal(String),
+    Column(String),
+}
+
+/// A line layout in which `{{column}}` placeholders are replaced by values.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl Template {
+    /// Parses a template, interpreting `\t`, `\n`, `\r`, `\0` and `\\` escapes.
+    pub fn parse(s: &str) -> Result<Template> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") {
+                let end = rest.find("}}").ok_or_else(|| {
+                    Error::Usage(format!("unterminated placeholder in template: {}", rest))
+                })?;
+                let name = rest[2..end].trim();
+                if name.is_empty() {
+                    return Err(Error::Usage("empty placeholder in template".to_owned()));
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(TemplatePart::Column(name.to_owned()));
+                rest = &rest[end + 2..];
+                continue;
+            }
+            if c == '\\' {
+                if let Some(e) = rest[1..].chars().next().and_then(unescape_char) {
+                    literal.push(e);
+                    rest = &rest[2..];
+                    continue;
+                }
+            }
+            literal.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+}
+
+/// Writes one line per row laid out by a template.
+struct TemplateWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    template: Template,
+    /// Column index for each placeholder of the template, in order.
+    indices: Vec<usize>,
+}
+
+impl<W: Write> TemplateWriter<W> {
+    fn new(out: W, conv: Converter, template: Template) -> TemplateWriter<W> {
+        TemplateWriter {
+            out,
+            conv,
+            template,
+            indices: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> RowWriter for TemplateWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let names = column_names(columns);
+        let mut indices = Vec::new();
+        for part in &self.template.parts {
+            if let TemplatePart::Column(ref name) = *part {
+                let index = names.iter().position(|n| n == name).ok_or_else(|| {
+                    Error::Usage(format!("template refers to unknown column {:?} (available: {})", name, names.join(", ")))
+                })?;
+                indices.push(index);
+            }
+        }
+        self.indices = indices;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        let mut line = String::new();
+        let mut indices = self.indices.iter();
+        for part in &self.template.parts {
+            match *part {
+                TemplatePart::Literal(ref s) => line.push_str(s),
+                TemplatePart::Column(_) => {
+                    let &i = indices.next().expect("placeholders are resolved in begin");
+                    line.push_str(&self.conv.text(i, &row[i])?);
+                },
+            }
+        }
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{int_column, options, row, text_column};
+
+    #[test]
+    fn truncate_width_to_zero() {
+        assert_eq!(truncate_width("abc".to_owned(), 0), "");
+        assert_eq!(truncate_width("".to_owned(), 0), "");
+        assert_eq!(truncate_width("abc".to_owned(), 1), "…");
+        assert_eq!(truncate_width("abc".to_owned(), 2), "a…");
+        assert_eq!(truncate_width("abc".to_owned(), 3), "abc");
+    }
+
+    #[test]
+    fn msgpack_round_trip() {
+        let columns = [int_column("id"), text_column("name")];
+        let mut out = Vec::new();
+        {
+            let mut writer = Format::Msgpack.writer(&mut out, &options(&[])).unwrap();
+            writer.begin(&columns).unwrap();
+            writer.write_row(&row(&columns, vec![mysql::Value::Int(1), mysql::Value::Bytes("ünï".into())])).unwrap();
+            writer.write_row(&row(&columns, vec![mysql::Value::Int(-2), mysql::Value::NULL])).unwrap();
+            writer.end().unwrap();
+            writer.finish().unwrap();
+        }
+        let mut de = rmp_serde::Deserializer::new(&out[..]);
+        let mut rows: Vec<json::Value> = Vec::new();
+        while !de.get_ref().is_empty() {
+            rows.push(serde::Deserialize::deserialize(&mut de).unwrap());
+        }
+        assert_eq!(rows, vec![json::json!({"id": 1, "name": "ünï"}), json::json!({"id": -2, "name": null})]);
+    }
+
+    fn xml_fields(args: &[&str], columns: &[mysql::Column], values: Vec<mysql::Value>) -> Vec<String> {
+        let mut out = Vec::new();
+        {
+            let mut writer = Format::Xml.writer(&mut out, &options(args)).unwrap();
+            writer.begin(columns).unwrap();
+            writer.write_row(&row(columns, values)).unwrap();
+            writer.end().unwrap();
+        }
+        String::from_utf8(out).unwrap().lines().filter(|line| line.starts_with("\t<field")).map(|line| line.trim().to_owned()).collect()
+    }
+
+    #[test]
+    fn xml_by_column() {
+        let columns = [
+            make_column("latin1", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty(), 8, 80, 0),
+            make_column("blob", ColumnType::MYSQL_TYPE_BLOB, ColumnFlags::BINARY_FLAG | ColumnFlags::BLOB_FLAG, 63, 65535, 0),
+            make_column("bit", ColumnType::MYSQL_TYPE_BIT, ColumnFlags::UNSIGNED_FLAG, 63, 8, 0),
+            make_column("geom", ColumnType::MYSQL_TYPE_GEOMETRY, ColumnFlags::BINARY_FLAG | ColumnFlags::BLOB_FLAG, 63, 0, 0),
+            text_column("text"),
+        ];
+        let mut point = vec![0, 0, 0, 0, 1, 1, 0, 0, 0];
+        point.extend_from_slice(&1f64.to_le_bytes());
+        point.extend_from_slice(&2f64.to_le_bytes());
+        let values = || vec![
+            mysql::Value::Bytes(b"caf\xe9".to_vec()),
+            mysql::Value::Bytes(b"utf-8".to_vec()),
+            mysql::Value::Bytes(vec![5]),
+            mysql::Value::Bytes(point.clone()),
+            mysql::Value::Bytes("<ä>".into()),
+        ];
+        assert_eq!(xml_fields(&[], &columns, values()), [
+            "<field name=\"latin1\">café</field>",
+            "<field name=\"blob\" encoding=\"base64\">dXRmLTg=</field>",
+            "<field name=\"bit\">5</field>",
+            "<field name=\"geom\">POINT(1 2)</field>",
+            "<field name=\"text\">&lt;ä&gt;</field>",
+        ]);
+        let fields = xml_fields(&["--binary", "hex", "--bit", "binary"], &columns, values());
+        assert_eq!(fields[1..3], ["<field name=\"blob\" encoding=\"hex\">7574662d38</field>", "<field name=\"bit\" encoding=\"hex\">05</field>"]);
+    }
+
+    #[test]
+    fn raw_by_column() {
+        let raw = |column: mysql::Column, value: mysql::Value| {
+            let columns = [column];
+            let mut out = Vec::new();
+            {
+                let mut writer = Format::Raw.writer(&mut out, &options(&[])).unwrap();
+                writer.begin(&columns).unwrap();
+                writer.write_row(&row(&columns, vec![value])).unwrap();
+                writer.end().unwrap();
+            }
+            out
+        };
+        let mut point = vec![0, 0, 0, 0, 1, 1, 0, 0, 0];
+        point.extend_from_slice(&1f64.to_le_bytes());
+        point.extend_from_slice(&2f64.to_le_bytes());
+        // Strings are written byte for byte, and the rest as text.
+        let blob = make_column("blob", ColumnType::MYSQL_TYPE_BLOB, ColumnFlags::BINARY_FLAG | ColumnFlags::BLOB_FLAG, 63, 65535, 0);
+        assert_eq!(raw(blob, mysql::Value::Bytes(vec![0xff, 0])), [0xff, 0, b'\n']);
+        let geom = make_column("geom", ColumnType::MYSQL_TYPE_GEOMETRY, ColumnFlags::BINARY_FLAG | ColumnFlags::BLOB_FLAG, 63, 0, 0);
+        assert_eq!(raw(geom, mysql::Value::Bytes(point)), b"POINT(1 2)\n");
+        let bit = make_column("bit", ColumnType::MYSQL_TYPE_BIT, ColumnFlags::UNSIGNED_FLAG, 63, 8, 0);
+        assert_eq!(raw(bit, mysql::Value::Bytes(vec![5])), b"5\n");
+    }
+}
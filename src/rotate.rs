@@ -0,0 +1,208 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::compress::{compress_file, Compression};
+use crate::error::{Error, Result};
+use crate::output::{Counted, Format, OutputOptions, RowWriter};
+use crate::tail::parse_duration;
+use crate::{FileMode, Output};
+
+
+/// When `tail` moves on to a new output file.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotation {
+    /// Once this many bytes have been written to the file, before any
+    /// `--compress`.
+    pub size: Option<u64>,
+    /// Once the file has been open this long.
+    pub time: Option<Duration>,
+}
+
+/// Parses a rotation such as `size=100MB`, `time=1h` or both separated by a
+/// comma.  Sizes are in bytes or in KB, MB or GB of 1024 each.
+pub fn parse_rotation(s: &str) -> std::result::Result<Rotation, String> {
+    let mut rotation = Rotation { size: None, time: None };
+    for part in s.split(',') {
+        match part.split_once('=') {
+            Some(("size", value)) => rotation.size = Some(parse_size(value)?),
+            Some(("time", value)) => rotation.time = Some(parse_duration(value)?),
+            _ => return Err(format!("invalid rotation: {} (expected size=SIZE or time=DURATION)", part)),
+        }
+    }
+    Ok(rotation)
+}
+
+pub fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let invalid = || format!("invalid size: {} (expected a number followed by B, KB, MB or GB)", s);
+    let i = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(i);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let unit = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(unit).filter(|&size| size > 0).ok_or_else(invalid)
+}
+
+/// The file being written.
+struct Current {
+    path: String,
+    output: Output,
+    writer: Box<dyn RowWriter>,
+    written: Arc<AtomicU64>,
+    opened: Instant,
+}
+
+/// Writes rows to files named after the time they are opened, moving on to
+/// a new one between rows when the rotation says so.  Each file begins with
+/// the columns of the last result set, so that `csv` files have a header.
+pub struct RotatingWriter {
+    /// Path with `strftime` fields for the local time, and `{n}` for the
+    /// number of the file in the run.
+    template: String,
+    format: Format,
+    opts: OutputOptions,
+    mode: FileMode,
+    compression: Option<Compression>,
+    rotation: Rotation,
+    /// How to compress the files done with.
+    rotate_compress: Option<Compression>,
+    current: Option<Current>,
+    files: usize,
+    columns: Vec<mysql::Column>,
+    compressing: Vec<JoinHandle<()>>,
+    /// Bytes written to all of the files.
+    total: Arc<AtomicU64>,
+}
+
+impl RotatingWriter {
+    /// Opens the first file.
+    pub fn open(template: &str, format: Format, opts: OutputOptions, mode: FileMode, compression: Option<Compression>, rotation: Rotation, rotate_compress: Option<Compression>) -> Result<RotatingWriter> {
+        if !template.contains('%') && !template.contains("{n}") {
+            return Err(Error::Usage("--output-rotate requires --output to name the files with strftime fields such as %Y%m%d-%H%M or with {n}".to_owned()));
+        }
+        let mut writer = RotatingWriter {
+            template: template.to_owned(),
+            format,
+            opts,
+            mode,
+            compression,
+            rotation,
+            rotate_compress,
+            current: None,
+            files: 0,
+            columns: Vec::new(),
+            compressing: Vec::new(),
+            total: Arc::new(AtomicU64::new(0)),
+        };
+        let path = writer.next_path();
+        writer.current = Some(writer.create(path)?);
+        Ok(writer)
+    }
+
+    fn next_path(&self) -> String {
+        let path = self.template.replace("{n}", &(self.files + 1).to_string());
+        chrono::Local::now().format(&path).to_string()
+    }
+
+    fn create(&mut self, path: String) -> Result<Current> {
+        let (output, out) = Output::open(Some(&path), self.mode, self.compression)?;
+        let written = Arc::new(AtomicU64::new(0));
+        let out = Counted::new(Counted::new(out, self.total.clone()), written.clone());
+        let writer = self.format.writer(out, &self.opts)?;
+        self.files += 1;
+        Ok(Current { path, output, writer, written, opened: Instant::now() })
+    }
+
+    /// Closes the file, and has it compressed in the background if told to.
+    fn close(&mut self, mut current: Current) -> Result<()> {
+        current.writer.finish()?;
+        current.output.close()?;
+        if let Some(compression) = self.rotate_compress {
+            let path = current.path;
+            self.compressing.push(thread::spawn(move || {
+                if let Err(e) = compress_file(&path, compression) {
+                    eprintln!("rows: warning: {}: cannot compress: {}", path, e);
+                }
+            }));
+        }
+        Ok(())
+    }
+
+    /// Moves on to a new file if the current one is due for rotation and the
+    /// template gives a new name.
+    fn rotate(&mut self) -> Result<()> {
+        let due = match self.current {
+            Some(ref current) => {
+                self.rotation.size.is_some_and(|size| current.written.load(Ordering::Relaxed) >= size)
+                    || self.rotation.time.is_some_and(|time| current.opened.elapsed() >= time)
+            },
+            None => false,
+        };
+        if !due {
+            return Ok(());
+        }
+        let path = self.next_path();
+        if self.current.as_ref().is_some_and(|current| current.path == path) {
+            return Ok(());
+        }
+        if let Some(current) = self.current.take() {
+            self.close(current)?;
+        }
+        let mut current = self.create(path)?;
+        if !self.columns.is_empty() {
+            current.writer.begin(&self.columns)?;
+        }
+        self.current = Some(current);
+        Ok(())
+    }
+
+    /// Counts the bytes written to all of the files, before any `--compress`.
+    pub fn total(&self) -> Arc<AtomicU64> {
+        self.total.clone()
+    }
+
+    fn writer(&mut self) -> &mut dyn RowWriter {
+        &mut *self.current.as_mut().expect("a rotating writer has a file open until finished").writer
+    }
+}
+
+impl RowWriter for RotatingWriter {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.columns = columns.to_vec();
+        self.writer().begin(columns)
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        self.rotate()?;
+        self.writer().write_row(row)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.writer().end()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer().flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(current) = self.current.take() {
+            self.close(current)?;
+        }
+        for compressing in self.compressing.drain(..) {
+            let _ = compressing.join();
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&mut self, time: &str) -> Result<()> {
+        self.rotate()?;
+        self.writer().heartbeat(time)
+    }
+}
@@ -0,0 +1,175 @@
+/// Splits a script into statements on semicolons, or on the delimiter set by
+/// a `DELIMITER` line as for the bodies of triggers and procedures.  Nothing
+/// is split inside quoted strings, backtick identifiers or comments.  Parts
+/// with nothing but whitespace and comments are left out.
+pub fn statements(text: &str) -> Vec<String> {
+    let b = text.as_bytes();
+    let mut statements = Vec::new();
+    let mut delimiter = ";".to_owned();
+    let mut start = 0;
+    // Whether the statement has anything but whitespace and comments.
+    let mut content = false;
+    let mut i = 0;
+    while i < b.len() {
+        if !content {
+            if let Some((new, end)) = delimiter_line(text, i) {
+                delimiter = new;
+                i = end;
+                start = end;
+                continue;
+            }
+        }
+        if let Some((end, quoted)) = skip(b, i) {
+            content |= quoted;
+            i = end;
+        }
+        else if b[i..].starts_with(delimiter.as_bytes()) {
+            if content {
+                statements.push(text[start..i].to_owned());
+            }
+            i += delimiter.len();
+            start = i;
+            content = false;
+        }
+        else {
+            content |= !b[i].is_ascii_whitespace();
+            i += 1;
+        }
+    }
+    if content {
+        statements.push(text[start..].to_owned());
+    }
+    statements
+}
+
+/// Rewrites the `:name` placeholders of a statement to `?`, giving the names
+/// in order, along with whether it has `?` placeholders of its own.
+pub fn named_placeholders(sql: &str) -> (String, Vec<String>, bool) {
+    let b = sql.as_bytes();
+    let mut rewritten = String::with_capacity(sql.len());
+    let mut names = Vec::new();
+    let mut positional = false;
+    let (mut i, mut copied) = (0, 0);
+    while i < b.len() {
+        if let Some((end, _)) = skip(b, i) {
+            i = end;
+            continue;
+        }
+        let is_name = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+        match b[i] {
+            b'?' => positional = true,
+            // Not `:=`, nor a `:` right after a name as in a label.
+            b':' if b.get(i + 1).is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_') && (i == 0 || !is_name(b[i - 1])) => {
+                let end = b[i + 1..].iter().position(|&c| !is_name(c)).map_or(b.len(), |j| i + 1 + j);
+                rewritten.push_str(&sql[copied..i]);
+                rewritten.push('?');
+                names.push(sql[i + 1..end].to_owned());
+                i = end;
+                copied = end;
+                continue;
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    rewritten.push_str(&sql[copied..]);
+    (rewritten, names, positional)
+}
+
+/// Where a quoted string, backtick identifier or comment starting at `i`
+/// ends, and whether it is run rather than a comment.
+fn skip(b: &[u8], i: usize) -> Option<(usize, bool)> {
+    match b[i] {
+        quote @ (b'\'' | b'"' | b'`') => {
+            let mut j = i + 1;
+            while j < b.len() && b[j] != quote {
+                // Backticks do not escape.
+                if b[j] == b'\\' && quote != b'`' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            Some(((j + 1).min(b.len()), true))
+        },
+        b'#' => Some((line_end(b, i), false)),
+        // `--` starts a comment only when followed by whitespace.
+        b'-' if b.get(i + 1) == Some(&b'-') && b.get(i + 2).is_none_or(|c| c.is_ascii_whitespace()) => Some((line_end(b, i), false)),
+        b'/' if b.get(i + 1) == Some(&b'*') => {
+            let end = b[i + 2..].windows(2).position(|w| w == b"*/").map_or(b.len(), |j| i + 2 + j + 2);
+            // `/*! ... */` is run by MySQL.
+            Some((end, b.get(i + 2) == Some(&b'!')))
+        },
+        _ => None,
+    }
+}
+
+/// Where the line from `i` ends, past its newline.
+fn line_end(b: &[u8], i: usize) -> usize {
+    b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |j| i + j + 1)
+}
+
+/// Reads a `DELIMITER` line at `i`, giving the new delimiter and where the
+/// line ends.
+fn delimiter_line(text: &str, i: usize) -> Option<(String, usize)> {
+    const KEYWORD: &str = "delimiter";
+    let rest = text.as_bytes().get(i..i + KEYWORD.len() + 1)?;
+    if !rest[..KEYWORD.len()].eq_ignore_ascii_case(KEYWORD.as_bytes()) || !matches!(rest[KEYWORD.len()], b' ' | b'\t') {
+        return None;
+    }
+    let end = line_end(text.as_bytes(), i);
+    let delimiter = text[i + KEYWORD.len()..end].split_whitespace().next()?;
+    Some((delimiter.to_owned(), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semicolons_outside_quotes_and_comments() {
+        assert_eq!(statements("SELECT 1; SELECT 2;\n"), ["SELECT 1", " SELECT 2"]);
+        assert_eq!(statements("SELECT 'a;b', \"c;d\", `e;f`"), ["SELECT 'a;b', \"c;d\", `e;f`"]);
+        assert_eq!(statements(r"SELECT 'it\'s;'; SELECT 2"), [r"SELECT 'it\'s;'", " SELECT 2"]);
+        // Backslashes do not escape in backticks.
+        assert_eq!(statements(r"SELECT 1 AS `a\`; SELECT 2"), [r"SELECT 1 AS `a\`", " SELECT 2"]);
+        assert_eq!(statements("SELECT 1 -- one; two\n; # three;\nSELECT 2 /* four; */"), ["SELECT 1 -- one; two\n", " # three;\nSELECT 2 /* four; */"]);
+        // `--` without a space after it is an operator.
+        assert_eq!(statements("SELECT 1--1; SELECT 2"), ["SELECT 1--1", " SELECT 2"]);
+        assert_eq!(statements("-- only a comment;\n; /* and another */ ;  "), Vec::<String>::new());
+        // `/*! ... */` is run, so a statement of one counts.
+        assert_eq!(statements("/*!40101 SET NAMES utf8mb4 */; -- done"), ["/*!40101 SET NAMES utf8mb4 */"]);
+        assert_eq!(statements("SELECT 'unterminated; SELECT 2"), ["SELECT 'unterminated; SELECT 2"]);
+    }
+
+    #[test]
+    fn delimiter_lines() {
+        let script = "DROP TRIGGER IF EXISTS t;\n\
+                      DELIMITER //\n\
+                      CREATE TRIGGER t BEFORE INSERT ON x FOR EACH ROW\n\
+                      BEGIN\n  SET NEW.a = 1;\n  SET NEW.b = ';';\nEND //\n\
+                      delimiter ;\n\
+                      SELECT 1;";
+        assert_eq!(statements(script), [
+            "DROP TRIGGER IF EXISTS t",
+            "CREATE TRIGGER t BEFORE INSERT ON x FOR EACH ROW\nBEGIN\n  SET NEW.a = 1;\n  SET NEW.b = ';';\nEND ",
+            "SELECT 1",
+        ]);
+        // Only at the start of a statement, and as a word of its own.
+        assert_eq!(statements("SELECT delimiter FROM t; SELECT 2"), ["SELECT delimiter FROM t", " SELECT 2"]);
+        assert_eq!(statements("DELIMITERS;"), ["DELIMITERS"]);
+    }
+
+    #[test]
+    fn named() {
+        let (sql, names, positional) = named_placeholders("SELECT * FROM t WHERE a = :a AND b IN (:b_1, :a)");
+        assert_eq!((sql.as_str(), names, positional), ("SELECT * FROM t WHERE a = ? AND b IN (?, ?)", vec!["a".to_owned(), "b_1".to_owned(), "a".to_owned()], false));
+        // Not in literals, identifiers or comments.
+        let (sql, names, _) = named_placeholders("SELECT ':a', `:b`, \":c\" -- :d\n/* :e */ # :f\n, :g");
+        assert_eq!((sql.as_str(), names), ("SELECT ':a', `:b`, \":c\" -- :d\n/* :e */ # :f\n, ?", vec!["g".to_owned()]));
+        // Assignments, labels, times and numbers are left alone.
+        let sql = "SET @x := 1; l1: LOOP SELECT '12:30', 12:30; END LOOP l1";
+        assert_eq!(named_placeholders(sql), (sql.to_owned(), Vec::new(), false));
+        assert_eq!(named_placeholders("SELECT ? + :a"), ("SELECT ? + ?".to_owned(), vec!["a".to_owned()], true));
+        assert_eq!(named_placeholders("SELECT '?'"), ("SELECT '?'".to_owned(), Vec::new(), false));
+    }
+}
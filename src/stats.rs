@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use clap::arg_enum;
+use serde_json as json;
+
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum StatsFormat {
+        Text,
+        Json,
+    }
+}
+
+/// How much was written in how long.
+pub struct Stats {
+    pub elapsed: Duration,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+impl Stats {
+    /// Prints the figures to stderr, after what they are for such as
+    /// `("statement", 2)`, as `statement=2 elapsed=1.204s rows=10000
+    /// bytes=812345 rows_per_sec=8305.6` or as a JSON object.
+    pub fn print(&self, format: StatsFormat, what: Option<(&str, u64)>) {
+        let secs = self.elapsed.as_secs_f64();
+        let rows_per_sec = if secs > 0.0 { (self.rows as f64 / secs * 10.0).round() / 10.0 } else { 0.0 };
+        match format {
+            StatsFormat::Json => {
+                let mut line = json::Map::new();
+                if let Some((key, n)) = what {
+                    line.insert(key.to_owned(), n.into());
+                }
+                line.insert("elapsed".to_owned(), ((secs * 1000.0).round() / 1000.0).into());
+                line.insert("rows".to_owned(), self.rows.into());
+                line.insert("bytes".to_owned(), self.bytes.into());
+                line.insert("rows_per_sec".to_owned(), rows_per_sec.into());
+                eprintln!("{}", json::Value::Object(line));
+            },
+            StatsFormat::Text => {
+                let what = what.map_or_else(String::new, |(key, n)| format!("{}={} ", key, n));
+                eprintln!("{}elapsed={:.3}s rows={} bytes={} rows_per_sec={}", what, secs, self.rows, self.bytes, rows_per_sec);
+            },
+        }
+    }
+}
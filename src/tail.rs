@@ -0,0 +1,970 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use clap::arg_enum;
+use serde_derive::{Deserialize, Serialize};
+use serde_json as json;
+
+use crate::cursor::Cursor;
+use crate::error::{Error, Result};
+use crate::output::RowWriter;
+use crate::rotate::parse_size;
+use crate::stats::{Stats, StatsFormat};
+use crate::tunnel;
+
+
+/// Parses a duration such as `500ms`, `2s`, `1m` or `1h`.  A bare number is
+/// in seconds.
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let invalid = || format!("invalid duration: {} (expected a number followed by ms, s, m or h)", s);
+    let i = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(i);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| invalid())
+}
+
+/// How the wait after polls which found no new rows grows while the table
+/// is idle.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// Wait after a poll which found rows.
+    pub min: Duration,
+    /// Longest wait.
+    pub max: Duration,
+    /// What the wait is multiplied by after each poll which found none.
+    pub factor: f64,
+}
+
+/// Parses a backoff such as `min=100ms,max=30s,factor=2`.  Left out, they
+/// are 100ms, 30s and 2.
+pub fn parse_backoff(s: &str) -> std::result::Result<Backoff, String> {
+    let mut backoff = Backoff { min: Duration::from_millis(100), max: Duration::from_secs(30), factor: 2.0 };
+    for part in s.split(',') {
+        match part.split_once('=') {
+            Some(("min", value)) => backoff.min = parse_duration(value)?,
+            Some(("max", value)) => backoff.max = parse_duration(value)?,
+            Some(("factor", value)) => {
+                backoff.factor = value.parse().ok().filter(|factor: &f64| *factor >= 1.0)
+                    .ok_or_else(|| format!("invalid backoff factor: {} (expected a number of at least 1)", value))?;
+            },
+            _ => return Err(format!("invalid backoff: {} (expected min=DURATION, max=DURATION or factor=NUMBER)", part)),
+        }
+    }
+    if backoff.min > backoff.max {
+        return Err(format!("invalid backoff: {} (min is longer than max)", s));
+    }
+    Ok(backoff)
+}
+
+/// When `tail` flushes what it has written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flush {
+    /// After every row, for consumers which want rows as soon as they come.
+    EveryRow,
+    /// After every poll.
+    EveryBatch,
+    /// After a poll at most this often, and before waiting.
+    Interval(Duration),
+}
+
+/// Parses `every-row`, `every-batch` or an interval such as `interval=500ms`.
+pub fn parse_flush(s: &str) -> std::result::Result<Flush, String> {
+    match s {
+        "every-row" => Ok(Flush::EveryRow),
+        "every-batch" => Ok(Flush::EveryBatch),
+        _ => match s.strip_prefix("interval=") {
+            Some(value) => parse_duration(value).map(Flush::Interval),
+            None => Err(format!("invalid flush: {} (expected every-row, every-batch or interval=DURATION)", s)),
+        },
+    }
+}
+
+/// Most rows, or bytes of output, `tail` writes per second.
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimit {
+    Rows(f64),
+    Bytes(f64),
+}
+
+/// Parses a rate limit such as `5000rows/s` or `10MB/s`.
+pub fn parse_rate_limit(s: &str) -> std::result::Result<RateLimit, String> {
+    let invalid = || format!("invalid rate limit: {} (expected a number of rows or a size, such as 5000rows/s or 10MB/s)", s);
+    let amount = s.strip_suffix("/s").ok_or_else(invalid)?;
+    match amount.strip_suffix("rows") {
+        Some(rows) => rows.parse().ok().filter(|rows: &f64| *rows > 0.0).map(RateLimit::Rows).ok_or_else(invalid),
+        // A bare number could be either.
+        None if amount.ends_with(|c: char| c.is_ascii_alphabetic()) => parse_size(amount).map(|bytes| RateLimit::Bytes(bytes as f64)),
+        None => Err(invalid()),
+    }
+}
+
+/// A token bucket holding up to a second's worth of a rate limit, so that
+/// writing below the limit never waits.
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    /// The rows or bytes written when last taken from the bucket.
+    used: u64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Bucket {
+        Bucket { limit, tokens: Bucket::rate(limit), used: 0, last: Instant::now() }
+    }
+
+    fn rate(limit: RateLimit) -> f64 {
+        match limit {
+            RateLimit::Rows(rate) | RateLimit::Bytes(rate) => rate,
+        }
+    }
+
+    /// Takes what has been written since last time, given the rows and bytes
+    /// written so far, and tells how long to wait to keep to the limit.
+    fn take(&mut self, rows: u64, bytes: u64) -> Duration {
+        let rate = Bucket::rate(self.limit);
+        let used = match self.limit {
+            RateLimit::Rows(_) => rows,
+            RateLimit::Bytes(_) => bytes,
+        };
+        self.tokens = (self.tokens + rate * self.last.elapsed().as_secs_f64()).min(rate) - used.saturating_sub(self.used) as f64;
+        self.used = used;
+        self.last = Instant::now();
+        if self.tokens < 0.0 { Duration::from_secs_f64(-self.tokens / rate) } else { Duration::ZERO }
+    }
+}
+
+/// Where `tail` starts reading.
+#[derive(Clone)]
+pub enum Start {
+    /// After the rows already in the table.
+    End,
+    /// With the given number of rows already in the table.
+    Last(u64),
+    /// At the first row.
+    Beginning,
+    /// After the row with the given cursor value.
+    Since(String),
+}
+
+/// What `tail` follows and how.
+pub struct TailOptions {
+    pub tables: Vec<Table>,
+    /// Columns to read, or all.  The writer leaves out the cursor column if
+    /// it is not one of them.
+    pub columns: Vec<String>,
+    /// SQL condition rows have to meet.
+    pub filter: Option<String>,
+    pub start: Start,
+    /// Whether to skip every row with a cursor up to the greatest one when
+    /// starting, whatever else reads it again.  The state file is then only
+    /// written.
+    pub skip_existing: bool,
+    /// How long to wait after a poll which found no new rows.
+    pub interval: Duration,
+    /// How to wait longer instead, the longer no new rows are found.
+    pub backoff: Option<Backoff>,
+    /// Write a heartbeat record when no rows have been written for this long.
+    pub heartbeat: Option<Duration>,
+    /// When to flush the output.
+    pub flush: Flush,
+    /// How far back from a date and time cursor to read again, for rows
+    /// committed late with an earlier time.
+    pub overlap: Option<Duration>,
+    /// Column telling rows apart, along with the cursor, in the overlap and
+    /// among the rows written.
+    pub dedupe_key: Option<String>,
+    /// How many rows written to remember per table, so as to skip them if
+    /// read again.  By default, this is `DEDUPE_CACHE` for date and time
+    /// cursors and none for the others, read strictly after the cursor.
+    pub dedupe_cache: Option<usize>,
+    /// Most rows a poll reads from a table.  Polling goes on without waiting
+    /// while a poll reads as many.
+    pub batch_size: Option<u64>,
+    /// Limits on how fast to write, kept to by waiting between polls.
+    pub rate_limits: Vec<RateLimit>,
+    /// Counts the bytes written to the output, for limits on them.
+    pub written: Arc<AtomicU64>,
+    /// Stop after writing this many rows.
+    pub max_rows: Option<u64>,
+    /// Stop after running this long.
+    pub max_duration: Option<Duration>,
+    /// Stop once a poll finds no more rows to read instead of waiting.
+    pub until_caught_up: bool,
+    /// Stop after the first poll.
+    pub no_follow: bool,
+    /// Whether to stop when the columns read change rather than starting
+    /// over with the new ones.
+    pub strict_schema: bool,
+    /// When to tell the cursor.
+    pub print_cursor: PrintCursor,
+    /// How often to tell the cursor with `PrintCursor::Periodic`.
+    pub cursor_interval: Duration,
+    /// Whether to tell the cursor as JSON.
+    pub cursor_json: bool,
+    /// How to print how much has been written, if at all.
+    pub stats: Option<StatsFormat>,
+    /// How often to print the stats.
+    pub stats_interval: Duration,
+    /// File keeping the cursor across runs.
+    pub state_file: Option<String>,
+    /// Whether to disregard a state file written for another table.
+    pub force: bool,
+    /// Name of a column added in front of every row to tell which table it
+    /// is from.
+    pub table_field: Option<String>,
+    /// Whether all tables have to give the same columns, for formats which
+    /// do not name the fields of every row.
+    pub same_columns: bool,
+}
+
+arg_enum! {
+    /// When `tail` prints its cursors to stderr: every so often and on
+    /// stopping, only on stopping, or not at all.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum PrintCursor {
+        Periodic,
+        Exit,
+        Never,
+    }
+}
+
+/// A table `tail` follows, by its cursor column.
+pub struct Table {
+    pub name: String,
+    pub column: String,
+}
+
+/// Position in a table, as kept in a `--state-file`.
+#[derive(Serialize, Deserialize)]
+struct State {
+    table: String,
+    column: String,
+    cursor: Option<String>,
+}
+
+/// Contents of a `--state-file`: the state of the only table, or of each.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum States {
+    One(State),
+    Many(Vec<State>),
+}
+
+impl States {
+    fn load(path: &str) -> Result<Option<Vec<State>>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::File(path.to_owned(), e)),
+        };
+        match json::from_str(&content) {
+            Ok(States::One(state)) => Ok(Some(vec![state])),
+            Ok(States::Many(states)) => Ok(Some(states)),
+            Err(e) => Err(Error::Usage(format!("{}: invalid state file: {}", path, e))),
+        }
+    }
+
+    /// Replaces the file at once so that it is never seen half-written.
+    fn save(&self, path: &str) -> Result<()> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, json::to_string(self)?).map_err(|e| Error::File(tmp.clone(), e))?;
+        fs::rename(&tmp, path).map_err(|e| Error::File(path.to_owned(), e))
+    }
+}
+
+/// Works out where to start in a table from a state file, which stands in
+/// for `Start::End`: a start given explicitly wins over it.
+fn resume(opts: &TailOptions, table: &Table, states: &Option<Vec<State>>) -> Result<Start> {
+    let (path, states) = match (&opts.start, &opts.state_file, states) {
+        (&Start::End, Some(path), Some(states)) => (path, states),
+        _ => return Ok(opts.start.clone()),
+    };
+    match states.iter().find(|state| state.table == table.name && state.column == table.column) {
+        Some(&State { cursor: Some(ref cursor), .. }) => Ok(Start::Since(cursor.clone())),
+        Some(&State { cursor: None, .. }) => Ok(Start::Beginning),
+        None if opts.force => Ok(Start::End),
+        None => {
+            let tailed: Vec<String> = states.iter().map(|state| format!("{} by {}", state.table, state.column)).collect();
+            Err(Error::Usage(format!(
+                "{} is the state of tailing {}, not {} by {}; use --force to start over",
+                path, tailed.join(", "), table.name, table.column)))
+        },
+    }
+}
+
+/// Takes the cursor from a row read.
+fn cursor_of(row: &mysql::Row, column: &str) -> Result<Option<Cursor>> {
+    match row.get::<mysql::Value, _>(column) {
+        Some(value) => Cursor::from_value(&value),
+        None => Err(Error::Usage(format!("no column {} in the rows read", column))),
+    }
+}
+
+/// Reads the greatest cursor in a table.
+fn max_cursor(conn: &mut mysql::Conn, sql_max: &str) -> Result<Option<Cursor>> {
+    match conn.first_exec::<_, _, mysql::Row>(sql_max, ())? {
+        Some(row) => Cursor::from_value(&row["max_id"]),
+        None => Ok(None),
+    }
+}
+
+/// Prints the cursors to stderr, with how far behind the greatest one in
+/// their table they are if that can be told, as
+/// `cursor=18273645 lag_rows=12` or as JSON.  The table is named too when
+/// there are several.
+fn print_cursors(conn: &mut mysql::Conn, opts: &TailOptions, followers: &[Follower]) -> Result<()> {
+    for follower in followers {
+        let max = max_cursor(conn, &follower.sql_max)?;
+        let lag = match (&follower.last_id, &max) {
+            (Some(id), Some(max)) => id.lag(max),
+            _ => None,
+        };
+        let cursor = follower.last_id.as_ref().map(|id| id.to_string());
+        if opts.cursor_json {
+            let mut line = json::json!({ "table": follower.table.name, "cursor": cursor });
+            if let Some(lag) = lag {
+                line["lag_rows"] = lag.into();
+            }
+            if follower.recent.is_some() {
+                line["duplicates"] = follower.duplicates.into();
+            }
+            eprintln!("{}", line);
+            continue;
+        }
+        let mut line = String::new();
+        if followers.len() > 1 {
+            line.push_str(&format!("table={} ", logfmt(&follower.table.name)));
+        }
+        line.push_str(&format!("cursor={}", logfmt(cursor.as_deref().unwrap_or(""))));
+        if let Some(lag) = lag {
+            line.push_str(&format!(" lag_rows={}", lag));
+        }
+        if follower.recent.is_some() {
+            line.push_str(&format!(" duplicates={}", follower.duplicates));
+        }
+        eprintln!("{}", line);
+    }
+    Ok(())
+}
+
+/// Prints to stderr how fast rows and bytes have been written since
+/// `started`, as `rows_per_sec=4998.2 bytes_per_sec=1022361.5` or as JSON.
+fn print_rate(opts: &TailOptions, rows: u64, started: Instant) {
+    let secs = started.elapsed().as_secs_f64().max(0.001);
+    let rows_per_sec = (rows as f64 / secs * 10.0).round() / 10.0;
+    let bytes_per_sec = (opts.written.load(Ordering::Relaxed) as f64 / secs * 10.0).round() / 10.0;
+    if opts.cursor_json {
+        eprintln!("{}", json::json!({ "rows_per_sec": rows_per_sec, "bytes_per_sec": bytes_per_sec }));
+    }
+    else {
+        eprintln!("rows_per_sec={} bytes_per_sec={}", rows_per_sec, bytes_per_sec);
+    }
+}
+
+/// Quotes a value of a `key=value` line if it has spaces or the like.
+fn logfmt(value: &str) -> String {
+    if value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        format!("{:?}", value)
+    }
+    else {
+        value.to_owned()
+    }
+}
+
+/// Quotes an identifier with backticks.
+fn quote_ident(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Quotes a table name, which may be qualified by a database as in
+/// `db.table`.
+fn quote_table(name: &str) -> String {
+    match name.split_once('.') {
+        Some((database, table)) => format!("{}.{}", quote_ident(database), quote_ident(table)),
+        None => quote_ident(name),
+    }
+}
+
+/// Names the columns of a table, in order, or tells that there is no such
+/// table.
+fn table_columns(conn: &mut mysql::Conn, name: &str) -> Result<Vec<String>> {
+    let (database, table) = match name.split_once('.') {
+        Some((database, table)) => (Some(database), table),
+        None => (None, name),
+    };
+    let sql = "SELECT COLUMN_NAME FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = COALESCE(?, DATABASE()) AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION;";
+    let mut columns = Vec::new();
+    for row in conn.prep_exec(sql, (database, table))? {
+        columns.push(mysql::from_row::<String>(row?));
+    }
+    if columns.is_empty() {
+        let current: Option<String> = match database {
+            Some(_) => None,
+            None => conn.first::<_, Option<String>>("SELECT DATABASE();")?.unwrap_or(None),
+        };
+        return Err(Error::Usage(match (database, current) {
+            (Some(database), _) => format!("no table {} in database {}", table, database),
+            (None, Some(current)) => format!("no table {} in database {}", table, current),
+            (None, None) => format!("no table {}: no database is selected (use --database or write it as DB.{})", table, table),
+        }));
+    }
+    Ok(columns)
+}
+
+/// Tells a row in the overlap apart by its time and dedupe key.
+fn window_entry(row: &mysql::Row, column: &str, key: &str) -> Result<Option<(NaiveDateTime, String)>> {
+    match cursor_of(row, column)? {
+        Some(Cursor::DateTime(t)) => Ok(Some((t, row.get::<mysql::Value, _>(key).map_or_else(String::new, |v| v.as_sql(false))))),
+        _ => Ok(None),
+    }
+}
+
+/// Rows remembered by default by the dedupe cache.
+const DEDUPE_CACHE: usize = 10000;
+
+/// The keys of the rows written last, forgetting the oldest beyond a number.
+struct Recent {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl Recent {
+    fn new(capacity: usize) -> Recent {
+        Recent { keys: HashSet::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// Remembers a key, telling whether it is new.
+    fn insert(&mut self, key: String) -> bool {
+        if self.keys.contains(&key) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        self.keys.insert(key.clone());
+        self.order.push_back(key);
+        true
+    }
+}
+
+/// Tells a row written apart by its cursor, and by its dedupe key or, for a
+/// cursor which may be shared, by all of its values.
+fn recent_key(row: &mysql::Row, id: Option<&Cursor>, key: Option<&str>) -> String {
+    let rest = match key {
+        Some(key) => row.get::<mysql::Value, _>(key).map_or_else(String::new, |v| v.as_sql(false)),
+        None if id.is_some_and(|id| !id.is_shared()) => String::new(),
+        None => fingerprint(row),
+    };
+    format!("{}\0{}", id.map_or_else(String::new, |id| id.to_string()), rest)
+}
+
+/// Tells rows apart by all of their values.
+fn fingerprint(row: &mysql::Row) -> String {
+    (0..row.len()).map(|i| row.as_ref(i).map_or_else(String::new, |v| v.as_sql(false))).collect::<Vec<_>>().join(",")
+}
+
+/// Set once Ctrl-C is pressed or SIGTERM received, after `interrupted`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the run stops by itself once `INTERRUPTED` is set, rather than
+/// exiting at once.
+static STOPS: AtomicBool = AtomicBool::new(false);
+
+/// Has Ctrl-C and SIGTERM exit with status 130, closing the SSH tunnel
+/// first, as exiting skips dropping it.
+pub fn handle_interrupts() -> Result<()> {
+    static HANDLED: AtomicBool = AtomicBool::new(false);
+    if HANDLED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    ctrlc::set_handler(|| {
+        if STOPS.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        tunnel::exit(130);
+    }).map_err(|e| Error::Io(io::Error::other(e)))
+}
+
+/// Set once Ctrl-C is pressed or SIGTERM received, so that `tail` can stop
+/// between rows, and `query --transaction` roll back between statements.  A
+/// second Ctrl-C exits at once, for when the server does not answer.
+pub fn interrupted() -> Result<&'static AtomicBool> {
+    STOPS.store(true, Ordering::SeqCst);
+    handle_interrupts()?;
+    Ok(&INTERRUPTED)
+}
+
+/// Sleeps for `duration` unless interrupted first.
+fn sleep(duration: Duration, interrupted: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !interrupted.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+}
+
+/// A table being tailed, with where it has got to.
+struct Follower<'a> {
+    table: &'a Table,
+    /// The cursor column, named as in the table.
+    column: String,
+    /// The value of the cursor column in the last row read, if any.
+    last_id: Option<Cursor>,
+    /// Rows which have been read with the cursor as it is, if it may be
+    /// shared with rows yet to come.  These are read again by polling from
+    /// the cursor inclusive, and skipped.  Until the cursor moves on from
+    /// where tail started, polling is from the cursor exclusive.
+    seen: Option<HashSet<String>>,
+    /// With an overlap, the times and keys of the rows read which are in it.
+    /// These stand in for `seen`.
+    window: Option<BTreeSet<(NaiveDateTime, String)>>,
+    /// The dedupe key column, named as in the table.
+    key: Option<String>,
+    /// The greatest cursor when starting, with `skip_existing`.
+    floor: Option<Cursor>,
+    /// The rows written, with the dedupe cache.
+    recent: Option<Recent>,
+    /// How many rows read were skipped by the dedupe cache.
+    duplicates: u64,
+    sql_max: String,
+    // These are prepared once and then taken from the statement cache of the
+    // connection.  With a batch size, they end with a LIMIT placeholder.
+    sql_after: String,
+    sql_from: String,
+    sql_all: String,
+}
+
+/// Writes the rows of several tables to one writer, beginning a result set
+/// whenever the rows come from another table than the last, or come with
+/// other columns than before as after an `ALTER TABLE`.  With
+/// `same_columns`, the tables share one result set.
+struct Interleaved<'w> {
+    writer: &'w mut dyn RowWriter,
+    same_columns: bool,
+    /// Whether a change of columns is an error.
+    strict_schema: bool,
+    /// Whether to flush after every row.
+    flush_rows: bool,
+    current: Option<usize>,
+    names: Vec<String>,
+    rows: u64,
+}
+
+impl<'w> Interleaved<'w> {
+    fn switch(&mut self, table: usize, name: &str, columns: &[mysql::Column]) -> Result<()> {
+        let shared = match self.current {
+            Some(current) => current == table || self.same_columns,
+            None => false,
+        };
+        // Called for every row, so compared without copying the names.
+        let unchanged = self.names.len() == columns.len() && self.names.iter().zip(columns).all(|(n, c)| *n == c.name_str());
+        if shared && unchanged {
+            return Ok(());
+        }
+        let names: Vec<String> = columns.iter().map(|c| c.name_str().into_owned()).collect();
+        if shared {
+            // The other tables in the result set would no longer fit it.
+            if self.strict_schema || self.current != Some(table) {
+                return Err(Error::Schema(format!("the columns of {} changed from {} to {}", name, self.names.join(", "), names.join(", "))));
+            }
+        }
+        self.current = Some(table);
+        self.names = names;
+        self.writer.begin(columns)
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        self.rows += 1;
+        self.writer.write_row(row).map_err(|e| e.at_row(self.rows))?;
+        if self.flush_rows {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the cursors to the state file if there is one.
+fn save_cursors(opts: &TailOptions, followers: &[Follower]) -> Result<()> {
+    let path = match opts.state_file {
+        Some(ref path) => path,
+        None => return Ok(()),
+    };
+    let mut states: Vec<State> = followers.iter().map(|follower| State {
+        table: follower.table.name.clone(),
+        column: follower.table.column.clone(),
+        cursor: follower.last_id.as_ref().map(|id| id.to_string()),
+    }).collect();
+    if states.len() == 1 {
+        States::One(states.remove(0)).save(path)
+    }
+    else {
+        States::Many(states).save(path)
+    }
+}
+
+/// Writes rows added to the tables until interrupted or told to stop by the
+/// options, and tells how many rows were written.  `timed_out` names errors
+/// caused by a timeout.
+pub fn tail(conn: &mut mysql::Conn, opts: &TailOptions, writer: &mut dyn RowWriter, timed_out: &dyn Fn(Error) -> Error) -> Result<u64> {
+    // The --where predicate goes into every query, cursor condition or not.
+    let (filter, and_filter) = match opts.filter {
+        Some(ref filter) => (format!(" WHERE ({})", filter), format!(" AND ({})", filter)),
+        None => (String::new(), String::new()),
+    };
+    let interrupted = interrupted()?;
+    let started = Instant::now();
+    let deadline = opts.max_duration.map(|duration| started + duration);
+    let stopped = |rows: u64| {
+        interrupted.load(Ordering::SeqCst)
+            || opts.max_rows.is_some_and(|max| rows >= max)
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    };
+    let states = match (&opts.start, &opts.state_file) {
+        (&Start::End, Some(path)) => States::load(path)?,
+        _ => None,
+    };
+    let mut out = Interleaved {
+        writer,
+        same_columns: opts.same_columns,
+        strict_schema: opts.strict_schema,
+        flush_rows: opts.flush == Flush::EveryRow,
+        current: None,
+        names: Vec::new(),
+        rows: 0,
+    };
+    let mut followers = Vec::new();
+    // The column names of the first table, which the others have to match
+    // with `same_columns`.
+    let mut first_columns: Option<Vec<String>> = None;
+
+    for (i, table) in opts.tables.iter().enumerate() {
+        let available = table_columns(conn, &table.name).map_err(timed_out)?;
+        let find = |name: &str| available.iter().find(|c| c.eq_ignore_ascii_case(name)).ok_or_else(|| {
+            Error::Usage(format!("no column {} in {} (available: {})", name, table.name, available.join(", ")))
+        });
+        for name in &opts.columns {
+            find(name)?;
+        }
+        if let Some(ref field) = opts.table_field {
+            if find(field).is_ok() {
+                return Err(Error::Usage(format!("{} already has a column {}; name the table field otherwise with --table-field", table.name, field)));
+            }
+        }
+        if opts.same_columns && opts.columns.is_empty() {
+            match first_columns {
+                Some(ref first) if !first.iter().map(|s| s.to_lowercase()).eq(available.iter().map(|s| s.to_lowercase())) => {
+                    return Err(Error::Usage(format!(
+                        "{} has other columns than {} ({} instead of {}); choose the same ones with --columns or use --format json",
+                        table.name, opts.tables[0].name, available.join(", "), first.join(", "))));
+                },
+                Some(_) => (),
+                None => first_columns = Some(available.clone()),
+            }
+        }
+        // Named as in the table, which is how rows read with `*` name it.
+        let column = find(&table.column)?.clone();
+        let (name, quoted) = (quote_table(&table.name), quote_ident(&column));
+        let key = match opts.dedupe_key {
+            Some(ref key) => Some(find(key)?.clone()),
+            None => None,
+        };
+        let mut select = if opts.columns.is_empty() {
+            "*".to_owned()
+        }
+        else {
+            let mut columns: Vec<String> = opts.columns.iter().map(|c| quote_ident(c)).collect();
+            for needed in std::iter::once(&column).chain(&key) {
+                if !opts.columns.iter().any(|c| c.eq_ignore_ascii_case(needed)) {
+                    columns.push(quote_ident(needed));
+                }
+            }
+            columns.join(", ")
+        };
+        // Reading the cursor column also shows up a bad --where at once.
+        let cursor_column = {
+            let sql = format!(r#"SELECT {column} FROM {table}{filter} LIMIT 0;"#, table=name, column=quoted, filter=filter);
+            let result = conn.prep_exec(sql, ()).map_err(|e| timed_out(e.into()))?;
+            result.columns_ref()[0].clone()
+        };
+        Cursor::check_column(&cursor_column)?;
+        if opts.overlap.is_some() && !Cursor::is_datetime_column(&cursor_column) {
+            return Err(Error::Usage(format!("--overlap requires a DATETIME, TIMESTAMP or DATE column to follow, not {}", column)));
+        }
+        let mut recent = match opts.dedupe_cache {
+            Some(0) => None,
+            Some(n) => Some(Recent::new(n)),
+            None if Cursor::is_datetime_column(&cursor_column) => Some(Recent::new(DEDUPE_CACHE)),
+            None => None,
+        };
+        if let Some(ref field) = opts.table_field {
+            select = format!("{} AS {}, {}", mysql::Value::from(table.name.as_str()).as_sql(false), quote_ident(field), select);
+        }
+
+        let sql_max = format!(r#"SELECT max({column}) AS max_id FROM {table}{filter};"#, table=name, column=quoted, filter=filter);
+        let start = if opts.skip_existing { Start::End } else { resume(opts, table, &states)? };
+        let last_id = match start {
+            // An empty table gives NULL, after which every row is new.
+            Start::End => max_cursor(conn, &sql_max).map_err(timed_out)?,
+            Start::Last(n) => {
+                let sql = format!(r#"SELECT {select} FROM {table}{filter} ORDER BY {column} DESC LIMIT ?;"#, select=select, table=name, column=quoted, filter=filter);
+                let result = conn.prep_exec(sql, (n, )).map_err(|e| timed_out(e.into()))?;
+                out.switch(i, &table.name, result.columns_ref())?;
+                let mut last = result.collect::<std::result::Result<Vec<mysql::Row>, _>>().map_err(|e| timed_out(e.into()))?;
+                last.reverse();
+                let mut last_id = None;
+                for row in &last {
+                    if stopped(out.rows) {
+                        break;
+                    }
+                    out.write_row(row)?;
+                    last_id = cursor_of(row, &column)?;
+                    if let Some(ref mut recent) = recent {
+                        recent.insert(recent_key(row, last_id.as_ref(), key.as_deref()));
+                    }
+                }
+                out.writer.flush()?;
+                last_id
+            },
+            Start::Beginning => None,
+            Start::Since(value) => Some(Cursor::parse(&value, &cursor_column)?),
+        };
+        // Rows already in the overlap are not new.
+        let window = match (opts.overlap, &key) {
+            (Some(overlap), Some(key)) => {
+                let mut window = BTreeSet::new();
+                if let Some(ref id) = last_id {
+                    let sql = format!(r#"SELECT {column}, {key} FROM {table} WHERE {column} >= ? AND {column} <= ?{filter};"#,
+                                      table=name, column=quoted, key=quote_ident(key), filter=and_filter);
+                    for row in conn.prep_exec(sql, (id.before(overlap).to_value(), id.to_value())).map_err(|e| timed_out(e.into()))? {
+                        let row = row.map_err(|e| timed_out(e.into()))?;
+                        if let Some(entry) = window_entry(&row, &column, key)? {
+                            window.insert(entry);
+                        }
+                    }
+                }
+                Some(window)
+            },
+            _ => None,
+        };
+        let floor = if opts.skip_existing { last_id.clone() } else { None };
+        let limit = if opts.batch_size.is_some() { " LIMIT ?" } else { "" };
+        followers.push(Follower {
+            table,
+            column,
+            last_id,
+            sql_max,
+            seen: None,
+            window,
+            key,
+            floor,
+            recent,
+            duplicates: 0,
+            sql_after: format!(r#"SELECT {select} FROM {table} WHERE {column} > ?{filter} ORDER BY {column}{limit};"#, select=select, table=name, column=quoted, filter=and_filter, limit=limit),
+            sql_from: format!(r#"SELECT {select} FROM {table} WHERE {column} >= ?{filter} ORDER BY {column}{limit};"#, select=select, table=name, column=quoted, filter=and_filter, limit=limit),
+            sql_all: format!(r#"SELECT {select} FROM {table}{filter} ORDER BY {column}{limit};"#, select=select, table=name, column=quoted, filter=filter, limit=limit),
+        });
+    }
+    // The cursors are kept from the start, and once more however polling
+    // ends, so that a run which wrote nothing can still be resumed.
+    save_cursors(opts, &followers)?;
+
+    let stats = |rows: u64| Stats { elapsed: started.elapsed(), rows, bytes: opts.written.load(Ordering::Relaxed) };
+    let polled = (|| -> Result<()> {
+        let mut wait = opts.backoff.map_or(opts.interval, |backoff| backoff.min);
+        // When rows or a heartbeat were last written.
+        let mut last_output = Instant::now();
+        let mut last_report = Instant::now();
+        let mut last_flush = Instant::now();
+        let mut last_stats = Instant::now();
+        let mut buckets: Vec<Bucket> = opts.rate_limits.iter().map(|&limit| Bucket::new(limit)).collect();
+        while !stopped(out.rows) {
+            let mut new_rows = 0;
+            // Whether a poll read a full batch, and so may have left rows behind.
+            let mut full = false;
+            for (i, follower) in followers.iter_mut().enumerate() {
+                if stopped(out.rows) {
+                    break;
+                }
+                let (sql, mut params) = match (&follower.last_id, &follower.seen, opts.overlap) {
+                    (Some(id), _, Some(overlap)) => (&follower.sql_from, vec![id.before(overlap).to_value()]),
+                    (Some(id), Some(_), None) => (&follower.sql_from, vec![id.to_value()]),
+                    (Some(id), None, None) => (&follower.sql_after, vec![id.to_value()]),
+                    (None, _, _) => (&follower.sql_all, Vec::new()),
+                };
+                // Rows read again to be skipped do not count towards the batch,
+                // or a batch of them could hold up the cursor for good.
+                let again = follower.seen.as_ref().map_or(0, |seen| seen.len()) + follower.window.as_ref().map_or(0, |window| window.len());
+                let limit = opts.batch_size.map(|n| n + again as u64);
+                if let Some(limit) = limit {
+                    params.push(mysql::Value::from(limit));
+                }
+                let result: mysql::QueryResult = conn.prep_exec(sql, params).map_err(|e| timed_out(e.into()))?;
+                // Output with a header starts with it, rows or not.
+                if out.current.is_none() {
+                    out.switch(i, &follower.table.name, result.columns_ref())?;
+                }
+                let columns = result.columns_ref().to_vec();
+                let mut table_rows = 0;
+                let mut read = 0;
+                for row in result {
+                    if stopped(out.rows) {
+                        break;
+                    }
+                    let row: mysql::Row = row.map_err(|e| timed_out(e.into()))?;
+                    read += 1;
+                    let id = cursor_of(&row, &follower.column)?;
+                    if let Some(ref floor) = follower.floor {
+                        if id.as_ref().is_none_or(|id| id <= floor) {
+                            continue;
+                        }
+                    }
+                    if let (Some(window), Some(key)) = (&mut follower.window, &follower.key) {
+                        if let Some(entry) = window_entry(&row, &follower.column, key)? {
+                            if !window.insert(entry) {
+                                continue;
+                            }
+                        }
+                    }
+                    else if let (Some(id), Some(last_id), Some(seen)) = (&id, &follower.last_id, &mut follower.seen) {
+                        if id == last_id && !seen.insert(fingerprint(&row)) {
+                            continue;
+                        }
+                    }
+                    if let Some(ref mut recent) = follower.recent {
+                        if !recent.insert(recent_key(&row, id.as_ref(), follower.key.as_deref())) {
+                            follower.duplicates += 1;
+                            continue;
+                        }
+                    }
+                    table_rows += 1;
+                    out.switch(i, &follower.table.name, &columns)?;
+                    out.write_row(&row)?;
+
+                    if let Some(id) = id {
+                        if follower.last_id.as_ref().is_none_or(|last_id| id > *last_id) {
+                            follower.seen = if id.is_shared() && follower.window.is_none() { Some(HashSet::from([fingerprint(&row)])) } else { None };
+                            follower.last_id = Some(id);
+                        }
+                    }
+                }
+                // Rows fallen out of the overlap are not read again.
+                if let (Some(window), Some(id), Some(overlap)) = (&mut follower.window, &follower.last_id, opts.overlap) {
+                    if let Cursor::DateTime(low) = id.before(overlap) {
+                        *window = window.split_off(&(low, String::new()));
+                    }
+                }
+                new_rows += table_rows;
+                full |= limit.is_some_and(|limit| read >= limit);
+                // Waiting between polls slows down the reading as well.  The
+                // bytes are counted once flushed.
+                if !buckets.is_empty() {
+                    out.writer.flush()?;
+                    let bytes = opts.written.load(Ordering::Relaxed);
+                    let mut pause = buckets.iter_mut().map(|bucket| bucket.take(out.rows, bytes)).max().unwrap_or_default();
+                    if let Some(deadline) = deadline {
+                        pause = pause.min(deadline.saturating_duration_since(Instant::now()));
+                    }
+                    sleep(pause, interrupted);
+                }
+            }
+            if new_rows > 0 {
+                last_output = Instant::now();
+            }
+            else if opts.heartbeat.is_some_and(|heartbeat| last_output.elapsed() >= heartbeat) {
+                out.writer.heartbeat(&chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string())?;
+                last_output = Instant::now();
+            }
+            // With an interval, polls in quick succession are flushed together.
+            if !matches!(opts.flush, Flush::Interval(interval) if last_flush.elapsed() < interval) {
+                out.writer.flush()?;
+                last_flush = Instant::now();
+            }
+            if opts.print_cursor == PrintCursor::Periodic && last_report.elapsed() >= opts.cursor_interval {
+                print_cursors(conn, opts, &followers).map_err(timed_out)?;
+                if !opts.rate_limits.is_empty() {
+                    print_rate(opts, out.rows, started);
+                }
+                last_report = Instant::now();
+            }
+            if let Some(format) = opts.stats {
+                if last_stats.elapsed() >= opts.stats_interval {
+                    stats(out.rows).print(format, None);
+                    last_stats = Instant::now();
+                }
+            }
+            if new_rows > 0 {
+                save_cursors(opts, &followers)?;
+                if let Some(backoff) = opts.backoff {
+                    wait = backoff.min;
+                }
+            }
+            // A poll which found rows may have left more behind, so only an
+            // empty one waits before the next; in batches, one which was not full.
+            let caught_up = if opts.batch_size.is_some() { !full } else { new_rows == 0 };
+            if opts.no_follow {
+                break;
+            }
+            if caught_up {
+                if opts.until_caught_up {
+                    break;
+                }
+                if let Flush::Interval(_) = opts.flush {
+                    out.writer.flush()?;
+                    last_flush = Instant::now();
+                }
+                let mut interval = wait;
+                if let Some(deadline) = deadline {
+                    interval = interval.min(deadline.saturating_duration_since(Instant::now()));
+                }
+                if let Some(heartbeat) = opts.heartbeat {
+                    interval = interval.min(heartbeat.saturating_sub(last_output.elapsed()));
+                }
+                if opts.print_cursor == PrintCursor::Periodic {
+                    interval = interval.min(opts.cursor_interval.saturating_sub(last_report.elapsed()));
+                }
+                if opts.stats.is_some() {
+                    interval = interval.min(opts.stats_interval.saturating_sub(last_stats.elapsed()));
+                }
+                sleep(interval, interrupted);
+                if let (Some(backoff), 0) = (opts.backoff, new_rows) {
+                    wait = wait.mul_f64(backoff.factor).min(backoff.max);
+                }
+            }
+        }
+        Ok(())
+    })();
+    let saved = save_cursors(opts, &followers);
+    polled?;
+    saved?;
+    if opts.print_cursor != PrintCursor::Never {
+        print_cursors(conn, opts, &followers).map_err(timed_out)?;
+        if !opts.rate_limits.is_empty() {
+            print_rate(opts, out.rows, started);
+        }
+    }
+    if let Some(format) = opts.stats {
+        out.writer.flush()?;
+        stats(out.rows).print(format, None);
+    }
+    Ok(out.rows)
+}
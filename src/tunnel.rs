@@ -0,0 +1,130 @@
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+
+/// The `ssh` child process of the open tunnel, kept where the Ctrl-C
+/// handler can get at it.
+static CHILD: Mutex<Option<Child>> = Mutex::new(None);
+
+fn child() -> MutexGuard<'static, Option<Child>> {
+    CHILD.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// An SSH tunnel to the server through a bastion host, run by an `ssh -L`
+/// child process which is killed when this is dropped, or by `exit` on
+/// Ctrl-C.
+pub struct Tunnel {
+    port: u16,
+}
+
+impl Tunnel {
+    /// Forwards a free local port through `ssh`, given as `user@bastion` or
+    /// `user@bastion:port`, to `host:port` as seen from the bastion, and
+    /// waits for the forwarding to be set up for at most `timeout` seconds.
+    pub fn open(ssh: &str, key: Option<&str>, host: &str, port: u16, timeout: Option<u64>) -> Result<Tunnel> {
+        let (destination, ssh_port) = match ssh.rsplit_once(':') {
+            Some((destination, ssh_port)) if !ssh_port.is_empty() && ssh_port.bytes().all(|b| b.is_ascii_digit()) => {
+                let ssh_port: u16 = ssh_port.parse().map_err(|_| Error::Usage(format!("invalid port in --ssh: {}", ssh_port)))?;
+                (destination, Some(ssh_port))
+            },
+            _ => (ssh, None),
+        };
+        let local_port = {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                .map_err(|e| Error::Tunnel(format!("no local port to forward: {}", e)))?;
+            listener.local_addr().map_err(|e| Error::Tunnel(format!("no local port to forward: {}", e)))?.port()
+        };
+
+        let mut command = Command::new("ssh");
+        command.arg("-N")
+               .arg("-o").arg("ExitOnForwardFailure=yes")
+               .arg("-L").arg(format!("127.0.0.1:{}:{}:{}", local_port, bracket(host), port));
+        if let Some(ssh_port) = ssh_port {
+            command.arg("-p").arg(ssh_port.to_string());
+        }
+        if let Some(key) = key {
+            command.arg("-i").arg(key);
+        }
+        command.arg(destination)
+               .stdin(Stdio::null())
+               .stdout(Stdio::null())
+               .stderr(Stdio::piped());
+        // Kept out of our process group so that Ctrl-C at the terminal
+        // leaves the tunnel up until we are done with it.
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        *child() = Some(command.spawn().map_err(|e| Error::Tunnel(format!("cannot run ssh: {}", e)))?);
+        // Closes the tunnel if it cannot be set up.
+        let tunnel = Tunnel { port: local_port };
+
+        let deadline = timeout.map(|secs| Instant::now() + Duration::from_secs(secs));
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, local_port));
+        loop {
+            if let Some(child) = child().as_mut() {
+                if let Some(status) = child.try_wait().map_err(|e| Error::Tunnel(format!("ssh: {}", e)))? {
+                    let mut stderr = String::new();
+                    if let Some(ref mut out) = child.stderr {
+                        let _ = out.read_to_string(&mut stderr);
+                    }
+                    return Err(Error::Tunnel(format!("ssh exited with {}: {}", status, stderr.trim())));
+                }
+            }
+            if TcpStream::connect_timeout(&addr, Duration::from_millis(100)).is_ok() {
+                // Nobody reads what ssh says from now on, so it is thrown
+                // away lest ssh block on a full pipe.
+                if let Some(mut stderr) = child().as_mut().and_then(|child| child.stderr.take()) {
+                    thread::spawn(move || io::copy(&mut stderr, &mut io::sink()));
+                }
+                return Ok(tunnel);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::Tunnel(format!("ssh did not set up forwarding within {}s", timeout.unwrap_or(0))));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Local port forwarded to the server.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        close();
+    }
+}
+
+fn close() {
+    if let Some(mut child) = child().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Kills the `ssh` child of the open tunnel, if any, and exits, as exiting
+/// skips dropping the tunnel.  The child is held on to until then, so that
+/// the run does not see the tunnel go first and fail on it.
+pub fn exit(status: i32) -> ! {
+    let mut child = child();
+    if let Some(ref mut child) = *child {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    process::exit(status)
+}
+
+/// Encloses an IPv6 address in brackets as `ssh -L` wants it.
+fn bracket(host: &str) -> String {
+    if host.contains(':') { format!("[{}]", host) } else { host.to_owned() }
+}
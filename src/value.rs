@@ -0,0 +1,1098 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::rc::Rc;
+use std::str::{self, FromStr};
+
+use chrono::format::{Fixed, Item, StrftimeItems};
+use chrono::prelude::*;
+use clap::arg_enum;
+use mysql::consts::{ColumnFlags, ColumnType};
+use serde_json as json;
+
+use crate::error::{Error, Result};
+use crate::geometry::Geometry;
+use crate::zone::{localize, Zone};
+
+
+/// Collation id of the `binary` character set.
+const BINARY_CHARSET: u16 = 63;
+
+arg_enum! {
+    /// How DECIMAL values are written to JSON.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Decimal {
+        String,
+        Number,
+    }
+}
+
+arg_enum! {
+    /// How BIT values are written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Bit {
+        Binary,
+        Int,
+        Bool,
+    }
+}
+
+arg_enum! {
+    /// Whether values of JSON columns are parsed or kept as text.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum JsonColumn {
+        Parse,
+        Raw,
+    }
+}
+
+arg_enum! {
+    /// How the bytes of binary values are written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Binary {
+        Base64,
+        Hex,
+        Escape,
+    }
+}
+
+arg_enum! {
+    /// How TIME values are written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum TimeFormat {
+        Mysql,
+        Iso8601,
+        Seconds,
+    }
+}
+
+/// Unit of the Unix timestamps written for DATETIME-like values by
+/// `--datetime-as`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Epoch {
+    Seconds,
+    Millis,
+    Micros,
+}
+
+/// Names accepted by `--datetime-as`.
+pub const EPOCHS: &[&str] = &["epoch-seconds", "epoch-millis", "epoch-micros"];
+
+impl FromStr for Epoch {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Epoch, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "epoch-seconds" => Ok(Epoch::Seconds),
+            "epoch-millis" => Ok(Epoch::Millis),
+            "epoch-micros" => Ok(Epoch::Micros),
+            _ => Err(format!("unknown timestamp unit: {}", s)),
+        }
+    }
+}
+
+arg_enum! {
+    /// How zero and other invalid dates such as `0000-00-00` are written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum ZeroDate {
+        Null,
+        Literal,
+        Error,
+    }
+}
+
+arg_enum! {
+    /// How NaN and infinite floating-point values are written to JSON.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum NonFinite {
+        Null,
+        String,
+        Error,
+    }
+}
+
+arg_enum! {
+    /// How integers too large for a JavaScript number are written to JSON.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum BigNumbers {
+        String,
+        Number,
+    }
+}
+
+/// Largest magnitude up to which every integer is exactly representable as
+/// a double, and thus survives `JSON.parse`.
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+arg_enum! {
+    /// How values of text columns which are not valid UTF-8 are written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum InvalidUtf8 {
+        Replace,
+        Base64,
+        Error,
+    }
+}
+
+arg_enum! {
+    /// How the zero value of YEAR columns is written.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum ZeroYear {
+        Zero,
+        Null,
+    }
+}
+
+/// How GEOMETRY values are written.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum GeometryFormat {
+    Wkt,
+    GeoJson,
+    WkbHex,
+}
+
+/// Names accepted by `--geometry`.
+pub const GEOMETRY_FORMATS: &[&str] = &["wkt", "geojson", "wkb-hex"];
+
+impl FromStr for GeometryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<GeometryFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "wkt" => Ok(GeometryFormat::Wkt),
+            "geojson" => Ok(GeometryFormat::GeoJson),
+            "wkb-hex" => Ok(GeometryFormat::WkbHex),
+            _ => Err(format!("unknown geometry format: {}", s)),
+        }
+    }
+}
+
+/// Conversion forced on a column by `--column-format`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ColumnFormat {
+    Hex,
+    Base64,
+    String,
+    Number,
+    Bool,
+    Epoch,
+    Uuid,
+}
+
+/// Names accepted after `NAME=` by `--column-format`.
+pub const COLUMN_FORMATS: &[&str] = &["hex", "base64", "string", "number", "bool", "epoch", "uuid"];
+
+impl FromStr for ColumnFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ColumnFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(ColumnFormat::Hex),
+            "base64" => Ok(ColumnFormat::Base64),
+            "string" => Ok(ColumnFormat::String),
+            "number" => Ok(ColumnFormat::Number),
+            "bool" => Ok(ColumnFormat::Bool),
+            "epoch" => Ok(ColumnFormat::Epoch),
+            "uuid" => Ok(ColumnFormat::Uuid),
+            _ => Err(format!("unknown column format: {} (expected one of {})", s, COLUMN_FORMATS.join(", "))),
+        }
+    }
+}
+
+/// Parses a `NAME=FORMAT` argument of `--column-format`.
+pub fn parse_column_format(s: &str) -> std::result::Result<(String, ColumnFormat), String> {
+    let (name, format) = s.split_once('=').ok_or_else(|| format!("expected NAME=FORMAT: {}", s))?;
+    Ok((name.to_owned(), format.parse()?))
+}
+
+/// Conversions forced on columns by name, shared by all the result sets of
+/// a run.
+#[derive(Debug, Default)]
+pub struct ColumnFormats {
+    formats: Vec<(String, ColumnFormat)>,
+    /// Whether BINARY(16) columns hold UUIDs unless told otherwise.
+    uuid_auto: bool,
+    /// Names already reported as missing from a result set.
+    missing: RefCell<HashSet<String>>,
+}
+
+impl ColumnFormats {
+    pub fn new(formats: Vec<(String, ColumnFormat)>, uuid_auto: bool) -> ColumnFormats {
+        ColumnFormats {
+            formats,
+            uuid_auto,
+            missing: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Looks up the format of each column, warning once about each name
+    /// which does not match any of them.
+    fn resolve(&self, columns: &[mysql::Column]) -> Vec<Option<ColumnFormat>> {
+        for (name, _) in &self.formats {
+            if !columns.iter().any(|c| c.name_str().eq_ignore_ascii_case(name)) && self.missing.borrow_mut().insert(name.to_lowercase()) {
+                eprintln!("rows: warning: --column-format: no column named {}", name);
+            }
+        }
+        columns.iter().map(|c| {
+            self.formats.iter().rev().find(|&(name, _)| c.name_str().eq_ignore_ascii_case(name)).map(|&(_, format)| format)
+                .or(if self.uuid_auto && is_binary16(c) { Some(ColumnFormat::Uuid) } else { None })
+        }).collect()
+    }
+}
+
+/// Settings which control how values are converted for output.
+#[derive(Debug, Clone)]
+pub struct ValueOptions {
+    /// Offset in which DATETIME-like values are interpreted.
+    pub tz: Option<Zone>,
+    pub decimal: Decimal,
+    pub bit: Bit,
+    pub json_column: JsonColumn,
+    pub geometry: GeometryFormat,
+    /// Whether TINYINT(1) columns hold booleans.
+    pub bool_tinyint1: bool,
+    pub binary: Binary,
+    /// Whether hexadecimal binary values start with `0x`.
+    pub binary_prefix: bool,
+    pub time: TimeFormat,
+    /// strftime-like format of DATETIME-like values, instead of RFC 3339.
+    pub datetime_format: Option<String>,
+    /// Write DATETIME-like values as Unix timestamps instead.
+    pub datetime_as: Option<Epoch>,
+    pub zero_date: ZeroDate,
+    pub non_finite: NonFinite,
+    pub big_numbers: BigNumbers,
+    /// Write SET values to JSON as arrays of their members.
+    pub set_as_array: bool,
+    /// Tell binary values from text by whether they are valid UTF-8, as
+    /// older versions did, instead of by the column metadata.
+    pub legacy_sniff: bool,
+    pub invalid_utf8: InvalidUtf8,
+    pub column_formats: Rc<ColumnFormats>,
+    pub zero_year: ZeroYear,
+    /// Write DATETIME-like values as the wall-clock time MySQL prints.
+    pub naive_datetime: bool,
+}
+
+/// Converts the values of a result set, taking its column metadata into
+/// account.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    opts: ValueOptions,
+    columns: Vec<mysql::Column>,
+    /// `--column-format` of each column.
+    formats: Vec<Option<ColumnFormat>>,
+    /// Warnings already printed for the current result set.
+    warned: RefCell<HashSet<(usize, &'static str)>>,
+}
+
+impl Converter {
+    pub fn new(opts: ValueOptions) -> Converter {
+        Converter {
+            opts,
+            columns: Vec::new(),
+            formats: Vec::new(),
+            warned: RefCell::new(HashSet::new()),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "parquet-output", feature = "arrow-output")), allow(dead_code))]
+    pub fn tz(&self) -> Option<Zone> {
+        self.opts.tz
+    }
+
+    #[cfg_attr(not(any(feature = "parquet-output", feature = "arrow-output")), allow(dead_code))]
+    pub fn zero_date(&self) -> ZeroDate {
+        self.opts.zero_date
+    }
+
+    /// Starts a result set with the given columns.
+    pub fn begin(&mut self, columns: &[mysql::Column]) {
+        self.columns = columns.to_vec();
+        self.formats = self.opts.column_formats.resolve(columns);
+        self.warned.borrow_mut().clear();
+    }
+
+    /// Prints a warning about the `i`-th column, unless it has been printed
+    /// for the column before.
+    fn warn_once(&self, i: usize, msg: &'static str) {
+        if self.warned.borrow_mut().insert((i, msg)) {
+            eprintln!("rows: warning: column {}: {}", self.columns[i].name_str(), msg);
+        }
+    }
+
+    /// Converts the value of the `i`-th column to JSON.
+    pub fn json(&self, i: usize, val: &mysql::Value) -> Result<json::Value> {
+        match self.formats[i] {
+            Some(format) if *val != mysql::Value::NULL => self.forced(i, format, val),
+            _ => self.column_json(i, val),
+        }
+    }
+
+    /// Converts the value of the `i`-th column to text, as written by CSV and
+    /// the other textual formats.
+    pub fn text(&self, i: usize, val: &mysql::Value) -> Result<String> {
+        match self.formats[i] {
+            Some(format) if *val != mysql::Value::NULL => Ok(json_text(self.forced(i, format, val)?)),
+            _ => self.column_text(i, val),
+        }
+    }
+
+    /// Name of the encoding `text` gives the value of the `i`-th column in,
+    /// if that is the bytes of a binary value encoded as `--binary` or
+    /// `--column-format` says rather than text.
+    pub fn encoding(&self, i: usize, val: &mysql::Value) -> Option<&'static str> {
+        let column = &self.columns[i];
+        let bytes = match *val {
+            mysql::Value::Bytes(ref bytes) => bytes,
+            _ => return None,
+        };
+        match self.formats[i] {
+            Some(ColumnFormat::Hex) => return Some("hex"),
+            Some(ColumnFormat::Base64) => return Some("base64"),
+            Some(ColumnFormat::String) | None => {},
+            Some(_) => return None,
+        }
+        let converted = is_bit(column) && self.opts.bit != Bit::Binary
+            || is_json(column) && self.opts.json_column == JsonColumn::Parse
+            || is_geometry(column);
+        let encoded = !converted && if self.opts.legacy_sniff { str::from_utf8(bytes).is_err() } else { is_binary(column) };
+        match self.opts.binary {
+            _ if !encoded => None,
+            Binary::Base64 => Some("base64"),
+            Binary::Hex => Some("hex"),
+            Binary::Escape => None,
+        }
+    }
+
+    /// Converts a value as `--column-format` says.
+    fn forced(&self, i: usize, format: ColumnFormat, val: &mysql::Value) -> Result<json::Value> {
+        let column = &self.columns[i];
+        let mismatch = || Error::Value(format!("a value in column {} cannot be written as {}", column.name_str(), format!("{:?}", format).to_lowercase()));
+        Ok(match format {
+            ColumnFormat::Hex | ColumnFormat::Base64 => {
+                let bytes = match *val {
+                    mysql::Value::Bytes(ref bytes) => bytes,
+                    _ => return Err(mismatch()),
+                };
+                json::Value::String(if format == ColumnFormat::Hex { hex(bytes) } else { base64::encode(bytes) })
+            },
+            ColumnFormat::String => json::Value::String(self.column_text(i, val)?),
+            ColumnFormat::Number => match *val {
+                mysql::Value::Int(num) => num.into(),
+                mysql::Value::UInt(num) => num.into(),
+                mysql::Value::Float(num) => json::Number::from_f64(num).map(json::Value::Number).ok_or_else(mismatch)?,
+                mysql::Value::Bytes(ref bytes) if is_bit(column) => bytes.iter().fold(0u64, |n, &b| (n << 8) | b as u64).into(),
+                mysql::Value::Bytes(ref bytes) => {
+                    let text = str::from_utf8(bytes).map_err(|_| mismatch())?;
+                    json::Value::Number(text.trim().parse().map_err(|_| mismatch())?)
+                },
+                _ => return Err(mismatch()),
+            },
+            ColumnFormat::Bool => match *val {
+                mysql::Value::Int(num) => json::Value::Bool(num != 0),
+                mysql::Value::UInt(num) => json::Value::Bool(num != 0),
+                mysql::Value::Bytes(ref bytes) if is_bit(column) => json::Value::Bool(bytes.iter().any(|&b| b != 0)),
+                mysql::Value::Bytes(ref bytes) => match bytes.as_slice() {
+                    b"0" => json::Value::Bool(false),
+                    b"1" => json::Value::Bool(true),
+                    _ => return Err(mismatch()),
+                },
+                _ => return Err(mismatch()),
+            },
+            ColumnFormat::Uuid => match *val {
+                mysql::Value::Bytes(ref bytes) if bytes.len() == 16 => json::Value::String(format_uuid(bytes)),
+                _ => {
+                    self.warn_once(i, "value is not a 16-byte UUID, writing it as is");
+                    self.column_json(i, val)?
+                },
+            },
+            ColumnFormat::Epoch => match *val {
+                mysql::Value::Date(..) => self.epoch(column, val, self.opts.datetime_as.unwrap_or(Epoch::Seconds))?.into(),
+                _ => return Err(mismatch()),
+            },
+        })
+    }
+
+    /// Converts a value according to the type of its column.
+    fn column_json(&self, i: usize, val: &mysql::Value) -> Result<json::Value> {
+        let column = &self.columns[i];
+        if is_year(column) && *val != mysql::Value::NULL {
+            return Ok(self.year(column, val)?.map_or(json::Value::Null, json::Value::from));
+        }
+        if let Some(b) = self.tinyint1_bool(column, val) {
+            return Ok(json::Value::Bool(b));
+        }
+        match *val {
+            mysql::Value::Bytes(ref bytes) if is_decimal(column) => {
+                let text = str::from_utf8(bytes).map_err(|_| Error::Value(format!("invalid DECIMAL value in column {}", column.name_str())))?;
+                match self.opts.decimal {
+                    Decimal::String => Ok(json::Value::String(text.to_owned())),
+                    Decimal::Number => decimal_number(text, column.decimals()).ok_or_else(|| {
+                        Error::Value(format!("DECIMAL value {} in column {} cannot be represented as a JSON number without losing precision", text, column.name_str()))
+                    }),
+                }
+            },
+            mysql::Value::Bytes(ref bytes) if is_bit(column) && self.opts.bit != Bit::Binary => {
+                self.bit_value(column, bytes)
+            },
+            mysql::Value::Bytes(ref bytes) if is_json(column) && self.opts.json_column == JsonColumn::Parse => {
+                // Anything the server sends should parse, but keep the text if not.
+                match json::from_slice(bytes) {
+                    Ok(parsed) => Ok(parsed),
+                    Err(_) => self.generic_json(val),
+                }
+            },
+            mysql::Value::Bytes(ref bytes) if is_geometry(column) => Ok(self.geometry_value(i, bytes)),
+            mysql::Value::Bytes(ref bytes) if self.opts.set_as_array && is_set(column) => {
+                let text = self.bytes_text(column, bytes)?;
+                let members = text.split(',').filter(|m| !m.is_empty()).map(|m| json::Value::String(m.to_owned()));
+                Ok(json::Value::Array(members.collect()))
+            },
+            mysql::Value::Bytes(ref bytes) => Ok(json::Value::String(self.bytes_text(column, bytes)?)),
+            mysql::Value::Int(num) if self.opts.big_numbers == BigNumbers::String && num.unsigned_abs() > MAX_SAFE_INTEGER => {
+                Ok(json::Value::String(num.to_string()))
+            },
+            mysql::Value::UInt(num) if self.opts.big_numbers == BigNumbers::String && num > MAX_SAFE_INTEGER => {
+                Ok(json::Value::String(num.to_string()))
+            },
+            mysql::Value::Float(num) if !num.is_finite() => match self.opts.non_finite {
+                NonFinite::Null => Ok(json::Value::Null),
+                NonFinite::String => Ok(json::Value::String(non_finite_text(num))),
+                NonFinite::Error => Err(self.non_finite_error(column, num)),
+            },
+            mysql::Value::Date(..) if to_naive_datetime(val).is_none() => {
+                Ok(self.invalid_date(column, val)?.map_or(json::Value::Null, json::Value::String))
+            },
+            mysql::Value::Date(year, month, day, ..) if is_date(column) => Ok(json::Value::String(format_date(year, month, day))),
+            mysql::Value::Time(..) => Ok(self.time_value(column, val)),
+            mysql::Value::Date(..) if self.opts.naive_datetime => Ok(json::Value::String(date_text(val))),
+            mysql::Value::Date(..) if self.opts.tz.is_none() && self.opts.datetime_format.is_none() => Err(self.no_timezone(column)),
+            mysql::Value::Date(..) if self.opts.datetime_format.is_some() => Ok(json::Value::String(self.format_datetime(column, val)?)),
+            mysql::Value::Date(..) if self.opts.datetime_as.is_some() => Ok(json::Value::Number(self.epoch(column, val, self.opts.datetime_as.unwrap())?.into())),
+            _ => self.generic_json(val),
+        }
+    }
+
+    fn column_text(&self, i: usize, val: &mysql::Value) -> Result<String> {
+        let column = &self.columns[i];
+        if is_year(column) && *val != mysql::Value::NULL {
+            return Ok(self.year(column, val)?.map_or_else(String::new, |year| format!("{:04}", year)));
+        }
+        if let Some(b) = self.tinyint1_bool(column, val) {
+            return Ok(b.to_string());
+        }
+        match *val {
+            mysql::Value::Bytes(ref bytes) if is_bit(column) && self.opts.bit != Bit::Binary => {
+                Ok(self.bit_value(column, bytes)?.to_string())
+            },
+            mysql::Value::Bytes(ref bytes) if is_json(column) && self.opts.json_column == JsonColumn::Parse => {
+                match json::from_slice::<json::Value>(bytes) {
+                    Ok(parsed) => Ok(parsed.to_string()),
+                    Err(_) => self.generic_text(val),
+                }
+            },
+            mysql::Value::Bytes(ref bytes) if is_geometry(column) => Ok(json_text(self.geometry_value(i, bytes))),
+            mysql::Value::Bytes(ref bytes) => self.bytes_text(column, bytes),
+            mysql::Value::Float(num) if !num.is_finite() => match self.opts.non_finite {
+                NonFinite::Error => Err(self.non_finite_error(column, num)),
+                _ => Ok(non_finite_text(num)),
+            },
+            mysql::Value::Date(..) if to_naive_datetime(val).is_none() => Ok(self.invalid_date(column, val)?.unwrap_or_default()),
+            mysql::Value::Date(year, month, day, ..) if is_date(column) => Ok(format_date(year, month, day)),
+            mysql::Value::Time(..) => Ok(json_text(self.time_value(column, val))),
+            mysql::Value::Date(..) if self.opts.naive_datetime => Ok(date_text(val)),
+            mysql::Value::Date(..) if self.opts.tz.is_none() && self.opts.datetime_format.is_none() => Err(self.no_timezone(column)),
+            mysql::Value::Date(..) if self.opts.datetime_format.is_some() => self.format_datetime(column, val),
+            mysql::Value::Date(..) if self.opts.datetime_as.is_some() => Ok(self.epoch(column, val, self.opts.datetime_as.unwrap())?.to_string()),
+            _ => self.generic_text(val),
+        }
+    }
+
+    /// Text of a string or binary value.  Values of binary columns are always
+    /// encoded and values of text columns are always decoded, unless
+    /// `--legacy-sniff` asks to tell them apart by whether they are UTF-8.
+    fn bytes_text(&self, column: &mysql::Column, bytes: &[u8]) -> Result<String> {
+        if self.opts.legacy_sniff {
+            return Ok(match str::from_utf8(bytes) {
+                Ok(s) => s.to_owned(),
+                Err(_) => self.encode_binary(bytes),
+            });
+        }
+        if is_binary(column) {
+            return Ok(self.encode_binary(bytes));
+        }
+        if is_latin1(column) {
+            return Ok(decode_latin1(bytes));
+        }
+        match str::from_utf8(bytes) {
+            Ok(s) => Ok(s.to_owned()),
+            Err(_) => match self.opts.invalid_utf8 {
+                InvalidUtf8::Replace => Ok(String::from_utf8_lossy(bytes).into_owned()),
+                InvalidUtf8::Base64 => Ok(base64::encode(bytes)),
+                InvalidUtf8::Error => Err(Error::Value(format!("invalid UTF-8 in column {}", column.name_str()))),
+            },
+        }
+    }
+
+    fn encode_binary(&self, bytes: &[u8]) -> String {
+        match self.opts.binary {
+            Binary::Base64 => base64::encode(bytes),
+            Binary::Hex if self.opts.binary_prefix => format!("0x{}", hex(bytes)),
+            Binary::Hex => hex(bytes),
+            Binary::Escape => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    fn no_timezone(&self, column: &mysql::Column) -> Error {
+        Error::Value(format!("DATETIME-like column {} requires --time-zone or --naive-datetime", column.name_str()))
+    }
+
+    fn non_finite_error(&self, column: &mysql::Column, num: f64) -> Error {
+        Error::Value(format!("non-finite value {} in column {}", non_finite_text(num), column.name_str()))
+    }
+
+    /// Reads a YEAR value, which arrives as an integer or as text depending on
+    /// the protocol.  Two-digit years of legacy YEAR(2) columns are expanded
+    /// the way MySQL does, and the zero year maps to `None` if asked to.
+    fn year(&self, column: &mysql::Column, val: &mysql::Value) -> Result<Option<u16>> {
+        let invalid = || Error::Value(format!("invalid YEAR value in column {}", column.name_str()));
+        let year: u16 = match *val {
+            mysql::Value::Int(num) => u16::try_from(num).map_err(|_| invalid())?,
+            mysql::Value::UInt(num) => u16::try_from(num).map_err(|_| invalid())?,
+            mysql::Value::Bytes(ref bytes) => str::from_utf8(bytes).ok().and_then(|s| s.trim().parse().ok()).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        Ok(match year {
+            0 if self.opts.zero_year == ZeroYear::Null => None,
+            0 => Some(0),
+            1..=69 if column.column_length() == 2 => Some(2000 + year),
+            70..=99 if column.column_length() == 2 => Some(1900 + year),
+            _ => Some(year),
+        })
+    }
+
+    /// Handles a zero date or another date which MySQL stores but which does
+    /// not exist, as `--zero-date` says.  `None` stands for NULL.
+    fn invalid_date(&self, column: &mysql::Column, val: &mysql::Value) -> Result<Option<String>> {
+        match self.opts.zero_date {
+            ZeroDate::Null => Ok(None),
+            ZeroDate::Literal => match *val {
+                mysql::Value::Date(year, month, day, ..) if is_date(column) => Ok(Some(format_date(year, month, day))),
+                _ => Ok(Some(date_text(val))),
+            },
+            ZeroDate::Error => Err(Error::Value(format!("invalid date {} in column {}", date_text(val), column.name_str()))),
+        }
+    }
+
+    /// Converts a value without regard to its column.
+    fn generic_json(&self, val: &mysql::Value) -> Result<json::Value> {
+        match self.opts.tz {
+            Some(Zone::Fixed(tz)) => to_json_value(val, Some(tz)),
+            Some(Zone::Named(tz)) => to_json_value(val, Some(tz)),
+            None => to_json_value::<FixedOffset>(val, None),
+        }
+    }
+
+    fn generic_text(&self, val: &mysql::Value) -> Result<String> {
+        match self.opts.tz {
+            Some(Zone::Fixed(tz)) => to_csv_value(val, Some(tz)),
+            Some(Zone::Named(tz)) => to_csv_value(val, Some(tz)),
+            None => to_csv_value::<FixedOffset>(val, None),
+        }
+    }
+
+    fn naive_datetime(&self, column: &mysql::Column, val: &mysql::Value) -> Result<NaiveDateTime> {
+        to_naive_datetime(val).ok_or_else(|| {
+            Error::Value(format!("invalid DATETIME value {} in column {}", date_text(val), column.name_str()))
+        })
+    }
+
+    /// Converts a DATETIME-like value to a Unix timestamp in the given unit.
+    fn epoch(&self, column: &mysql::Column, val: &mysql::Value, unit: Epoch) -> Result<i64> {
+        let naive = self.naive_datetime(column, val)?;
+        let tz = self.opts.tz.ok_or_else(|| {
+            Error::Value(format!("writing DATETIME column {} as a Unix timestamp requires --time-zone", column.name_str()))
+        })?;
+        let datetime = tz.to_utc(&naive).ok_or_else(|| {
+            Error::Value(format!("DATETIME value {} in column {} does not exist in timezone {}", date_text(val), column.name_str(), tz))
+        })?;
+        Ok(match unit {
+            Epoch::Seconds => datetime.timestamp(),
+            Epoch::Millis => datetime.timestamp_millis(),
+            Epoch::Micros => datetime.timestamp_micros(),
+        })
+    }
+
+    /// Formats a DATETIME-like value with `--datetime-format`.
+    fn format_datetime(&self, column: &mysql::Column, val: &mysql::Value) -> Result<String> {
+        let fmt = self.opts.datetime_format.as_deref().unwrap();
+        let naive = self.naive_datetime(column, val)?;
+        let nonexistent = || {
+            Error::Value(format!("DATETIME value {} in column {} does not exist in timezone {}", date_text(val), column.name_str(), self.opts.tz.unwrap()))
+        };
+        Ok(match self.opts.tz {
+            Some(Zone::Fixed(tz)) => localize(&tz, &naive).ok_or_else(nonexistent)?.format(fmt).to_string(),
+            Some(Zone::Named(tz)) => localize(&tz, &naive).ok_or_else(nonexistent)?.format(fmt).to_string(),
+            None => naive.format(fmt).to_string(),
+        })
+    }
+
+    /// Formats a TIME value.
+    fn time_value(&self, column: &mysql::Column, val: &mysql::Value) -> json::Value {
+        let (is_neg, hours, minutes, seconds, micros) = match *val {
+            mysql::Value::Time(is_neg, days, hours, minutes, seconds, micros) => {
+                (is_neg, days as u64 * 24 + hours as u64, minutes, seconds, micros)
+            },
+            _ => unreachable!(),
+        };
+        let sign = if is_neg { "-" } else { "" };
+        match self.opts.time {
+            TimeFormat::Mysql => {
+                let digits = column.decimals().min(6) as usize;
+                if digits == 0 {
+                    json::Value::String(format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds))
+                }
+                else {
+                    let frac = micros / 10u32.pow(6 - digits as u32);
+                    json::Value::String(format!("{}{:02}:{:02}:{:02}.{:0width$}", sign, hours, minutes, seconds, frac, width = digits))
+                }
+            },
+            TimeFormat::Iso8601 => {
+                let mut text = format!("{}PT", sign);
+                if hours > 0 {
+                    text.push_str(&format!("{}H", hours));
+                }
+                if minutes > 0 {
+                    text.push_str(&format!("{}M", minutes));
+                }
+                if micros > 0 {
+                    let frac = format!("{:06}", micros);
+                    text.push_str(&format!("{}.{}S", seconds, frac.trim_end_matches('0')));
+                }
+                else if seconds > 0 || (hours == 0 && minutes == 0) {
+                    text.push_str(&format!("{}S", seconds));
+                }
+                json::Value::String(text)
+            },
+            TimeFormat::Seconds => {
+                let secs = (hours * 3600 + minutes as u64 * 60 + seconds as u64) as i64;
+                let secs = if is_neg { -secs } else { secs };
+                if micros == 0 {
+                    json::Value::Number(json::Number::from(secs))
+                }
+                else {
+                    let micros = if is_neg { -(micros as f64) } else { micros as f64 };
+                    json::Number::from_f64(secs as f64 + micros / 1e6).map_or(json::Value::Null, json::Value::Number)
+                }
+            },
+        }
+    }
+
+    /// Reads a 0 or 1 in a TINYINT(1) column as a boolean if asked to.
+    /// Anything else is left alone so that a column which is not really
+    /// boolean is not corrupted.
+    fn tinyint1_bool(&self, column: &mysql::Column, val: &mysql::Value) -> Option<bool> {
+        if !self.opts.bool_tinyint1 || !is_tinyint1(column) {
+            return None;
+        }
+        match *val {
+            mysql::Value::Int(0) | mysql::Value::UInt(0) => Some(false),
+            mysql::Value::Int(1) | mysql::Value::UInt(1) => Some(true),
+            mysql::Value::Bytes(ref bytes) if bytes.as_slice() == b"0" => Some(false),
+            mysql::Value::Bytes(ref bytes) if bytes.as_slice() == b"1" => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Decodes a GEOMETRY value, falling back to the hexadecimal digits of
+    /// whatever the server sent if it cannot be decoded.
+    fn geometry_value(&self, i: usize, bytes: &[u8]) -> json::Value {
+        match Geometry::from_mysql(bytes) {
+            Some(geometry) => match self.opts.geometry {
+                GeometryFormat::Wkt => json::Value::String(geometry.to_wkt()),
+                GeometryFormat::GeoJson => geometry.to_geojson(),
+                GeometryFormat::WkbHex => json::Value::String(hex(&bytes[4..])),
+            },
+            None => {
+                self.warn_once(i, "cannot decode geometry, writing it as hex");
+                json::Value::String(hex(bytes))
+            },
+        }
+    }
+
+    /// Interprets the bytes of a BIT value as an integer, or as a boolean for
+    /// BIT(1) if asked to.
+    fn bit_value(&self, column: &mysql::Column, bytes: &[u8]) -> Result<json::Value> {
+        let num = bytes.iter().fold(0u64, |n, &b| (n << 8) | b as u64);
+        if self.opts.bit == Bit::Bool {
+            if column.column_length() != 1 {
+                return Err(Error::Value(format!("--bit bool requires a BIT(1) column, but {} is BIT({})", column.name_str(), column.column_length())));
+            }
+            return Ok(json::Value::Bool(num != 0));
+        }
+        Ok(json::Value::Number(json::Number::from(num)))
+    }
+}
+
+/// Text of a JSON value, without quotes if it is a string.
+fn json_text(val: json::Value) -> String {
+    match val {
+        json::Value::String(s) => s,
+        val => val.to_string(),
+    }
+}
+
+/// Formats bytes as lowercase hexadecimal digits.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether values of the column are binary strings, as opposed to text in
+/// some character set.
+fn is_binary(column: &mysql::Column) -> bool {
+    match column.column_type() {
+        ColumnType::MYSQL_TYPE_STRING
+        | ColumnType::MYSQL_TYPE_VAR_STRING
+        | ColumnType::MYSQL_TYPE_VARCHAR
+        | ColumnType::MYSQL_TYPE_TINY_BLOB
+        | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+        | ColumnType::MYSQL_TYPE_LONG_BLOB
+        | ColumnType::MYSQL_TYPE_BLOB
+        | ColumnType::MYSQL_TYPE_BIT => column.character_set() == BINARY_CHARSET,
+        _ => false,
+    }
+}
+
+/// Whether values of the column are strings, text or binary, which are
+/// bytes as they are.
+pub fn is_string(column: &mysql::Column) -> bool {
+    matches!(column.column_type(),
+        ColumnType::MYSQL_TYPE_STRING
+        | ColumnType::MYSQL_TYPE_VAR_STRING
+        | ColumnType::MYSQL_TYPE_VARCHAR
+        | ColumnType::MYSQL_TYPE_TINY_BLOB
+        | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+        | ColumnType::MYSQL_TYPE_LONG_BLOB
+        | ColumnType::MYSQL_TYPE_BLOB
+        | ColumnType::MYSQL_TYPE_ENUM
+        | ColumnType::MYSQL_TYPE_SET)
+}
+
+fn is_date(column: &mysql::Column) -> bool {
+    matches!(column.column_type(), ColumnType::MYSQL_TYPE_DATE | ColumnType::MYSQL_TYPE_NEWDATE)
+}
+
+/// Text of NaN or an infinity, as JavaScript spells them.
+fn non_finite_text(num: f64) -> String {
+    if num.is_nan() {
+        "NaN".to_owned()
+    }
+    else if num > 0.0 {
+        "Infinity".to_owned()
+    }
+    else {
+        "-Infinity".to_owned()
+    }
+}
+
+/// Formats a DATE value as `YYYY-MM-DD`.  It has no time of day, so no
+/// timezone is involved.
+fn format_date(year: u16, month: u8, day: u8) -> String {
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Whether the server sent values of the column in its `latin1` character
+/// set, which is really Windows-1252.
+fn is_latin1(column: &mysql::Column) -> bool {
+    matches!(column.character_set(), 5 | 8 | 15 | 31 | 47 | 48 | 49 | 94)
+}
+
+/// Characters of Windows-1252 for the bytes 0x80 to 0x9f.  MySQL maps the
+/// five bytes undefined there to the C1 control characters.
+const CP1252_C1: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        0x80..=0x9f => CP1252_C1[(b - 0x80) as usize],
+        _ => b as char,
+    }).collect()
+}
+
+fn is_binary16(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_STRING && column.character_set() == BINARY_CHARSET && column.column_length() == 16
+}
+
+/// Formats 16 bytes as a hyphenated UUID.
+fn format_uuid(bytes: &[u8]) -> String {
+    let hex = hex(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+fn is_year(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_YEAR
+}
+
+fn is_set(column: &mysql::Column) -> bool {
+    column.flags().contains(ColumnFlags::SET_FLAG)
+}
+
+fn is_tinyint1(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_TINY && column.column_length() == 1
+}
+
+fn is_geometry(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_GEOMETRY
+}
+
+fn is_json(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_JSON
+}
+
+fn is_bit(column: &mysql::Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_BIT
+}
+
+fn is_decimal(column: &mysql::Column) -> bool {
+    matches!(column.column_type(), ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL)
+}
+
+/// Parses a DECIMAL value with the given number of digits after the point into
+/// a JSON number, unless the nearest floating-point number does not fully
+/// preserve the value.
+fn decimal_number(text: &str, decimals: u8) -> Option<json::Value> {
+    let num: f64 = text.parse().ok()?;
+    if format!("{:.*}", decimals as usize, num) != text {
+        return None;
+    }
+    json::Number::from_f64(num).map(json::Value::Number)
+}
+
+/// Text of a DATETIME-like value as MySQL prints it, whether or not it is a
+/// valid date.
+fn date_text(val: &mysql::Value) -> String {
+    match *val {
+        mysql::Value::Date(year, month, day, hour, min, sec, 0) => {
+            format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, hour, min, sec)
+        },
+        mysql::Value::Date(year, month, day, hour, min, sec, usec) => {
+            format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}", year, month, day, hour, min, sec, usec)
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Checks a `--datetime-format` string up front, so that a bad one does not
+/// fail on every row.  Offsets can only be formatted if a timezone is given.
+pub fn check_datetime_format(fmt: &str, has_tz: bool) -> Result<()> {
+    for item in StrftimeItems::new(fmt) {
+        match item {
+            Item::Error => return Err(Error::Usage(format!("invalid --datetime-format: {}", fmt))),
+            Item::Fixed(Fixed::TimezoneName)
+            | Item::Fixed(Fixed::TimezoneOffsetColon)
+            | Item::Fixed(Fixed::TimezoneOffsetDoubleColon)
+            | Item::Fixed(Fixed::TimezoneOffsetTripleColon)
+            | Item::Fixed(Fixed::TimezoneOffsetColonZ)
+            | Item::Fixed(Fixed::TimezoneOffset)
+            | Item::Fixed(Fixed::TimezoneOffsetZ)
+            | Item::Fixed(Fixed::RFC2822)
+            | Item::Fixed(Fixed::RFC3339)
+            | Item::Fixed(Fixed::Internal(_)) if !has_tz => {
+                return Err(Error::Usage(format!("--datetime-format {} requires --time-zone", fmt)));
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// Builds the wall-clock time stored in a `Value::Date`, if it is a valid one.
+pub fn to_naive_datetime(val: &mysql::Value) -> Option<NaiveDateTime> {
+    match *val {
+        mysql::Value::Date(year, month, day, hour, min, sec, usec) => {
+            NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                .and_then(|date| date.and_hms_micro_opt(hour as u32, min as u32, sec as u32, usec))
+        },
+        _ => None,
+    }
+}
+
+/// Formats a TIME value as MySQL does, `[-]H:MM:SS[.ffffff]`.
+fn format_time(val: &mysql::Value) -> String {
+    match *val {
+        mysql::Value::Time(is_neg, days, hours, minutes, seconds, micros) => {
+            let sign = if is_neg { "-" } else { "" };
+            let hours = days as u64 * 24 + hours as u64;
+            if micros == 0 {
+                format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds)
+            }
+            else {
+                format!("{}{:02}:{:02}:{:02}.{:06}", sign, hours, minutes, seconds, micros)
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn to_rfc3339<T>(val: &mysql::Value, tz: Option<T>) -> Result<String> where T: TimeZone + Display, T::Offset: Display {
+    let tz = tz.ok_or_else(|| Error::Value("DATETIME-like values require --time-zone or --naive-datetime".to_owned()))?;
+    let naive = to_naive_datetime(val).ok_or_else(|| Error::Value(format!("invalid DATETIME value {}", date_text(val))))?;
+    let datetime = localize(&tz, &naive).ok_or_else(|| Error::Value(format!("DATETIME value {} does not exist in timezone {}", date_text(val), tz)))?;
+    Ok(datetime.to_rfc3339())
+}
+
+fn to_json_value<T>(val: &mysql::Value, tz: Option<T>) -> Result<json::Value> where T: TimeZone + Display, T::Offset: Display {
+    Ok(match *val {
+        mysql::Value::NULL => json::Value::Null,
+        mysql::Value::Bytes(ref bytes) => {
+            match str::from_utf8(bytes) {
+                Ok(s) => json::Value::String(s.to_owned()),
+                Err(_) => json::Value::String(base64::encode(bytes)),
+            }
+        },
+        mysql::Value::Int(num) => json::Value::Number(json::Number::from(num)),
+        mysql::Value::UInt(num) => json::Value::Number(json::Number::from(num)),
+        mysql::Value::Float(num) => json::Number::from_f64(num).map_or(json::Value::Null, json::Value::Number),
+        mysql::Value::Date(..) => json::Value::String(to_rfc3339(val, tz)?),
+        mysql::Value::Time(..) => json::Value::String(format_time(val)),
+    })
+}
+
+fn to_csv_value<T>(val: &mysql::Value, tz: Option<T>) -> Result<String> where T: TimeZone + Display, T::Offset: Display {
+    Ok(match *val {
+        mysql::Value::NULL => String::new(),
+        mysql::Value::Bytes(ref bytes) => {
+            match str::from_utf8(bytes) {
+                Ok(s) => s.to_owned(),
+                Err(_) => base64::encode(bytes),
+            }
+        },
+        mysql::Value::Int(num) => num.to_string(),
+        mysql::Value::UInt(num) => num.to_string(),
+        mysql::Value::Float(num) => num.to_string(),
+        mysql::Value::Date(..) => to_rfc3339(val, tz)?,
+        mysql::Value::Time(..) => format_time(val),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::make_column;
+    use crate::tests::options;
+
+    /// A converter of the given columns, as set up by these global options.
+    fn converter(args: &[&str], columns: &[mysql::Column]) -> Converter {
+        let mut conv = Converter::new(options(args).values);
+        conv.begin(columns);
+        conv
+    }
+
+    #[test]
+    fn decimal_as_number() {
+        assert_eq!(decimal_number("0.10", 2), Some(json::json!(0.1)));
+        assert_eq!(decimal_number("-12.50", 2), Some(json::json!(-12.5)));
+        assert_eq!(decimal_number("-123456789012345678.99", 2), None);
+
+        let columns = [make_column("d", ColumnType::MYSQL_TYPE_NEWDECIMAL, ColumnFlags::BINARY_FLAG, BINARY_CHARSET, 20, 2)];
+        let conv = converter(&["--decimal", "number"], &columns);
+        assert_eq!(conv.json(0, &mysql::Value::Bytes(b"0.10".to_vec())).unwrap(), json::json!(0.1));
+        assert_eq!(conv.json(0, &mysql::Value::NULL).unwrap(), json::Value::Null);
+        assert!(conv.json(0, &mysql::Value::Bytes(b"-123456789012345678.99".to_vec())).is_err());
+        let conv = converter(&[], &columns);
+        assert_eq!(conv.json(0, &mysql::Value::Bytes(b"-123456789012345678.99".to_vec())).unwrap(), json::json!("-123456789012345678.99"));
+    }
+
+    #[test]
+    fn negative_time_with_micros() {
+        use mysql::Value::Time;
+        assert_eq!(format_time(&Time(true, 1, 2, 3, 4, 500_000)), "-26:03:04.500000");
+        assert_eq!(format_time(&Time(true, 0, 0, 0, 0, 1)), "-00:00:00.000001");
+        assert_eq!(format_time(&Time(false, 34, 22, 59, 59, 0)), "838:59:59");
+
+        let columns = [make_column("t", ColumnType::MYSQL_TYPE_TIME, ColumnFlags::BINARY_FLAG, BINARY_CHARSET, 15, 3)];
+        let neg = Time(true, 0, 1, 2, 3, 456_000);
+        assert_eq!(converter(&[], &columns).json(0, &neg).unwrap(), json::json!("-01:02:03.456"));
+        assert_eq!(converter(&["--time-format", "iso8601"], &columns).json(0, &neg).unwrap(), json::json!("-PT1H2M3.456S"));
+        assert_eq!(converter(&["--time-format", "seconds"], &columns).json(0, &neg).unwrap(), json::json!(-3723.456));
+        assert_eq!(converter(&["--time-format", "seconds"], &columns).text(0, &Time(true, 0, 0, 0, 5, 0)).unwrap(), "-5");
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let columns = [make_column("f", ColumnType::MYSQL_TYPE_DOUBLE, ColumnFlags::BINARY_FLAG, BINARY_CHARSET, 22, 31)];
+        let values = [1e308 * 10.0, -1e308 * 10.0, f64::NAN].map(mysql::Value::Float);
+        let json = |args: &[&str]| values.iter().map(|val| converter(args, &columns).json(0, val).unwrap()).collect::<Vec<_>>();
+        let text = |args: &[&str]| values.iter().map(|val| converter(args, &columns).text(0, val).unwrap()).collect::<Vec<_>>();
+        assert_eq!(json(&[]), [json::Value::Null, json::Value::Null, json::Value::Null]);
+        assert_eq!(json(&["--non-finite", "string"]), [json::json!("Infinity"), json::json!("-Infinity"), json::json!("NaN")]);
+        assert_eq!(text(&[]), ["Infinity", "-Infinity", "NaN"]);
+        assert_eq!(text(&["--non-finite", "string"]), ["Infinity", "-Infinity", "NaN"]);
+        for val in &values {
+            assert!(converter(&["--non-finite", "error"], &columns).json(0, val).is_err());
+            assert!(converter(&["--non-finite", "error"], &columns).text(0, val).is_err());
+        }
+    }
+
+    #[test]
+    fn year_as_text_and_as_integer() {
+        use mysql::Value::{Bytes, Int};
+        let year = |length| [make_column("y", ColumnType::MYSQL_TYPE_YEAR, ColumnFlags::UNSIGNED_FLAG | ColumnFlags::ZEROFILL_FLAG, BINARY_CHARSET, length, 0)];
+        let conv = converter(&[], &year(4));
+        for val in &[Bytes(b"2024".to_vec()), Int(2024)] {
+            assert_eq!(conv.json(0, val).unwrap(), json::json!(2024));
+            assert_eq!(conv.text(0, val).unwrap(), "2024");
+        }
+        for val in &[Bytes(b"0000".to_vec()), Int(0)] {
+            assert_eq!(conv.json(0, val).unwrap(), json::json!(0));
+            assert_eq!(conv.text(0, val).unwrap(), "0000");
+            let conv = converter(&["--zero-year", "null"], &year(4));
+            assert_eq!(conv.json(0, val).unwrap(), json::Value::Null);
+            assert_eq!(conv.text(0, val).unwrap(), "");
+        }
+        assert_eq!(conv.json(0, &mysql::Value::NULL).unwrap(), json::Value::Null);
+        assert!(conv.json(0, &Bytes(b"20x4".to_vec())).is_err());
+
+        let conv = converter(&[], &year(2));
+        assert_eq!(conv.json(0, &Int(69)).unwrap(), json::json!(2069));
+        assert_eq!(conv.json(0, &Bytes(b"70".to_vec())).unwrap(), json::json!(1970));
+    }
+
+    #[test]
+    fn latin1_round_trip() {
+        let text = |charset| [make_column("s", ColumnType::MYSQL_TYPE_VAR_STRING, ColumnFlags::empty(), charset, 80, 0)];
+        // With `--charset latin1` the server sends the bytes of the column as
+        // they are, in Windows-1252.
+        let conv = converter(&[], &text(8));
+        assert_eq!(conv.text(0, &mysql::Value::Bytes(b"caf\xe9 \x80 \x9d".to_vec())).unwrap(), "café € \u{9d}");
+        assert_eq!(conv.json(0, &mysql::Value::Bytes(b"na\xefve".to_vec())).unwrap(), json::json!("naïve"));
+        // Otherwise it converts them to the utf8mb4 of the session.
+        let conv = converter(&[], &text(45));
+        assert_eq!(conv.text(0, &mysql::Value::Bytes("café € \u{9d}".into())).unwrap(), "café € \u{9d}");
+        assert_eq!(conv.text(0, &mysql::Value::Bytes(b"caf\xe9".to_vec())).unwrap(), "caf\u{fffd}");
+        for b in 0..=255u8 {
+            let decoded = decode_latin1(&[b]);
+            assert_eq!(decoded.chars().count(), 1);
+            // Outside 0x80 to 0x9f, latin1 is the first block of Unicode.
+            if !(0x80..=0x9f).contains(&b) {
+                assert_eq!(decoded, (b as char).to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn datetime_in_dst_gap_and_fold() {
+        use mysql::Value::Date;
+        let columns = [make_column("t", ColumnType::MYSQL_TYPE_DATETIME, ColumnFlags::BINARY_FLAG, BINARY_CHARSET, 19, 0)];
+        let conv = converter(&["--time-zone", "Europe/Berlin"], &columns);
+        assert!(conv.json(0, &Date(2024, 3, 31, 2, 30, 0, 0)).is_err());
+        assert_eq!(conv.json(0, &Date(2024, 10, 27, 2, 30, 0, 0)).unwrap(), json::json!("2024-10-27T02:30:00+02:00"));
+        assert_eq!(conv.json(0, &Date(2024, 10, 27, 3, 30, 0, 0)).unwrap(), json::json!("2024-10-27T03:30:00+01:00"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::Write;
+
+use mysql::consts::ColumnType;
+use rust_xlsxwriter::{Format as CellFormat, Workbook, Worksheet};
+
+use crate::error::Result;
+use crate::output::RowWriter;
+use crate::value::{to_naive_datetime, Converter};
+
+
+/// Number of rows a worksheet can hold, including the header row.
+const SHEET_ROWS: u32 = 1_048_576;
+
+/// Builds an Excel workbook with one worksheet per result set.
+///
+/// A result set which does not fit into a single worksheet continues on
+/// additional ones, each repeating the header row.  Worksheets are spilled to
+/// temporary files as they fill, but the finished workbook is assembled in
+/// memory before it is written out.
+pub struct XlsxWriter<W: Write> {
+    out: W,
+    conv: Converter,
+    workbook: Workbook,
+    header: Vec<String>,
+    types: Vec<ColumnType>,
+    result_sets: usize,
+    sheets: usize,
+    part: usize,
+    row: u32,
+    bold: CellFormat,
+    date: CellFormat,
+    datetime: CellFormat,
+}
+
+impl<W: Write> XlsxWriter<W> {
+    pub fn new(out: W, conv: Converter) -> XlsxWriter<W> {
+        XlsxWriter {
+            out,
+            conv,
+            workbook: Workbook::new(),
+            header: Vec::new(),
+            types: Vec::new(),
+            result_sets: 0,
+            sheets: 0,
+            part: 0,
+            row: 0,
+            bold: CellFormat::new().set_bold(),
+            date: CellFormat::new().set_num_format("yyyy-mm-dd"),
+            datetime: CellFormat::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+        }
+    }
+
+    /// Starts another worksheet for the current result set and writes its
+    /// header.
+    fn add_sheet(&mut self) -> Result<()> {
+        self.part += 1;
+        let name = if self.part == 1 {
+            format!("Result {}", self.result_sets)
+        }
+        else {
+            format!("Result {} ({})", self.result_sets, self.part)
+        };
+        let sheet: &mut Worksheet = self.workbook.add_worksheet_with_constant_memory();
+        sheet.set_name(name)?;
+        for (col, name) in self.header.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, name, &self.bold)?;
+        }
+        self.sheets += 1;
+        self.row = 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for XlsxWriter<W> {
+    fn begin(&mut self, columns: &[mysql::Column]) -> Result<()> {
+        self.conv.begin(columns);
+        self.header = columns.iter().map(|c| c.name_str().into_owned()).collect();
+        self.types = columns.iter().map(|c| c.column_type()).collect();
+        if !columns.is_empty() {
+            self.result_sets += 1;
+            self.part = 0;
+            self.add_sheet()?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &mysql::Row) -> Result<()> {
+        if self.row == SHEET_ROWS {
+            self.add_sheet()?;
+        }
+        let r = self.row;
+        let sheet = self.workbook.worksheet_from_index(self.sheets - 1)?;
+        for (i, &column_type) in self.types.iter().enumerate() {
+            let col = i as u16;
+            let val = &row[i];
+            match *val {
+                mysql::Value::NULL => {},
+                mysql::Value::Int(num) => { sheet.write_number(r, col, num as f64)?; },
+                mysql::Value::UInt(num) => { sheet.write_number(r, col, num as f64)?; },
+                mysql::Value::Float(num) => { sheet.write_number(r, col, num)?; },
+                mysql::Value::Date(..) => {
+                    match to_naive_datetime(val) {
+                        Some(dt) if column_type == ColumnType::MYSQL_TYPE_DATE => {
+                            sheet.write_datetime_with_format(r, col, dt.date(), &self.date)?;
+                        },
+                        Some(dt) => { sheet.write_datetime_with_format(r, col, dt, &self.datetime)?; },
+                        None => { sheet.write_string(r, col, self.conv.text(i, val)?)?; },
+                    }
+                },
+                _ => {
+                    let text = self.conv.text(i, val)?;
+                    let is_decimal = matches!(column_type, ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL);
+                    match text.parse::<f64>() {
+                        Ok(num) if is_decimal => { sheet.write_number(r, col, num)?; },
+                        _ => { sheet.write_string(r, col, text)?; },
+                    }
+                },
+            }
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.sheets == 0 {
+            self.workbook.add_worksheet();
+        }
+        let buf = self.workbook.save_to_buffer()?;
+        self.out.write_all(&buf)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use chrono_tz::Tz;
+
+
+/// Timezone given by `--time-zone`: a fixed offset or an IANA name.
+#[derive(Debug, Clone, Copy)]
+pub enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Zone, String> {
+        if let Ok(secs) = s.parse::<i32>() {
+            return FixedOffset::east_opt(secs).map(Zone::Fixed).ok_or_else(|| format!("invalid timezone offset: {}", s));
+        }
+        if s.starts_with('+') || s.starts_with('-') {
+            return parse_offset(s).map(Zone::Fixed).ok_or_else(|| format!("invalid timezone offset: {}", s));
+        }
+        s.parse::<Tz>().map(Zone::Named).map_err(|_| format!("unknown timezone: {}", s))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Zone::Fixed(ref tz) => tz.fmt(f),
+            Zone::Named(ref tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+/// Parses `+HH:MM` or `-HH:MM`.
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let (sign, rest) = s.split_at(1);
+    let (hours, minutes) = rest.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let secs = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(if sign == "-" { -secs } else { secs })
+}
+
+/// Attaches a timezone to a wall-clock time.  A time repeated when clocks go
+/// back is taken as the earlier of the two; a time skipped when they go
+/// forward does not exist.
+pub fn localize<T: TimeZone>(tz: &T, naive: &NaiveDateTime) -> Option<DateTime<T>> {
+    tz.from_local_datetime(naive).earliest()
+}
+
+impl Zone {
+    /// The instant a wall-clock time in this timezone refers to.
+    pub fn to_utc(self, naive: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            Zone::Fixed(tz) => localize(&tz, naive).map(|t| t.with_timezone(&Utc)),
+            Zone::Named(tz) => localize(&tz, naive).map(|t| t.with_timezone(&Utc)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin(s: &str) -> Option<DateTime<Utc>> {
+        let zone: Zone = "Europe/Berlin".parse().unwrap();
+        zone.to_utc(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap())
+    }
+
+    fn utc(s: &str) -> Option<DateTime<Utc>> {
+        Some(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap().and_utc())
+    }
+
+    #[test]
+    fn dst_gap_and_fold() {
+        // Clocks went forward from 02:00 to 03:00.
+        assert_eq!(berlin("2024-03-31 01:59:59"), utc("2024-03-31 00:59:59"));
+        assert_eq!(berlin("2024-03-31 02:30:00"), None);
+        assert_eq!(berlin("2024-03-31 03:00:00"), utc("2024-03-31 01:00:00"));
+        // Clocks went back from 03:00 to 02:00, and the earlier is taken.
+        assert_eq!(berlin("2024-10-27 02:30:00"), utc("2024-10-27 00:30:00"));
+        assert_eq!(berlin("2024-10-27 03:00:00"), utc("2024-10-27 02:00:00"));
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        let zone: Zone = "+09:00".parse().unwrap();
+        assert_eq!(zone.to_string(), "+09:00");
+        let naive = NaiveDateTime::parse_from_str("2024-03-31 02:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(zone.to_utc(&naive), utc("2024-03-30 17:30:00"));
+        assert!("+9:00".parse::<Zone>().is_err());
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+    }
+}